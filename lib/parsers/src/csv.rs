@@ -2,6 +2,8 @@ use std::path::Path;
 
 use serde::Deserialize;
 
+use crate::Sheet;
+
 /// Reads a CSV file from the given path and returns a vector of deserialized
 /// records.
 ///
@@ -44,6 +46,29 @@ where
     Ok(records)
 }
 
+/// Reads a CSV file from the given path as text, keeping its header row
+/// apart from the rows below it.
+///
+/// # Errors
+///
+/// Will return `Err` if `path` does not exist, if the user does not have
+/// permission to read it or if the file is not a valid CSV file.
+pub fn read_sheet<P: AsRef<Path>>(path: P) -> anyhow::Result<Sheet> {
+    let mut rdr = csv::Reader::from_path(path)?;
+
+    let headers = rdr.headers()?.iter().map(str::to_owned).collect();
+    let rows = rdr
+        .records()
+        .map(|record| Ok(record?.iter().map(str::to_owned).collect()))
+        .collect::<anyhow::Result<_>>()?;
+
+    Ok(Sheet {
+        name: String::new(),
+        headers,
+        rows,
+    })
+}
+
 pub fn write<T, P>(path: P, records: &[T]) -> anyhow::Result<()>
 where
     T: serde::Serialize,
@@ -1,10 +1,41 @@
-use std::path::Path;
+use std::{path::Path, str::FromStr};
 
-use calamine::{RangeDeserializerBuilder, Reader, Xlsx};
+use calamine::{DataType, Range, Reader};
 use serde::Deserialize;
 
+use crate::Sheet;
+
+/// Which worksheets of a workbook to read.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum Worksheets {
+    /// The worksheet with this name.
+    Name(String),
+    /// The worksheet at this position, counting from 0.
+    Index(usize),
+    /// Every worksheet with a header row; the others are left out.
+    #[default]
+    All,
+}
+
+impl FromStr for Worksheets {
+    type Err = anyhow::Error;
+
+    /// Parses "all", a position counting from 1, or otherwise a name.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.eq_ignore_ascii_case("all") {
+            return Ok(Self::All);
+        }
+
+        match s.parse::<usize>() {
+            Ok(0) => Err(anyhow::anyhow!("Worksheet positions count from 1")),
+            Ok(position) => Ok(Self::Index(position - 1)),
+            Err(_) => Ok(Self::Name(s.to_owned())),
+        }
+    }
+}
+
 /// Reads an Excel file from the given path and returns a vector of deserialized
-/// records.
+/// records, taken from every worksheet in turn.
 ///
 /// # Errors
 ///
@@ -16,20 +47,163 @@ where
     T: for<'de> Deserialize<'de>,
     P: AsRef<Path>,
 {
-    let mut workbook: Xlsx<_> = calamine::open_workbook(path)?;
     let mut records = Vec::new();
 
-    let range = workbook
-        .worksheet_range("Sheet1")
-        .ok_or(calamine::Error::Msg("Sheet not found"))??;
+    for sheet in read_sheets(path, &Worksheets::All)? {
+        records.extend(sheet.deserialize::<T>()?);
+    }
 
-    let iter: calamine::RangeDeserializer<calamine::DataType, T> =
-        RangeDeserializerBuilder::new().from_range(&range)?;
+    Ok(records)
+}
 
-    for result in iter {
-        let record: T = result?;
-        records.push(record);
+/// Reads the chosen worksheets of an Excel file from the given path as text.
+///
+/// The header row is taken to be the first row whose cells are all text and
+/// which is as wide as the widest row, so that title rows above it are
+/// skipped. Empty rows below it are left out.
+///
+/// # Errors
+///
+/// Will return `Err` if `path` does not exist, if the user does not have
+/// permission to read it, if the file is not a valid Excel file, if the
+/// chosen worksheet is not in it or if it has no header row.
+pub fn read_sheets<P: AsRef<Path>>(path: P, worksheets: &Worksheets) -> anyhow::Result<Vec<Sheet>> {
+    let mut workbook = calamine::open_workbook_auto(path)?;
+    let names = workbook.sheet_names().to_owned();
+
+    let chosen = match worksheets {
+        Worksheets::Name(name) => vec![names
+            .iter()
+            .find(|sheet| *sheet == name)
+            .cloned()
+            .ok_or_else(|| {
+                anyhow::anyhow!(
+                    "Worksheet {name} not found; found {}",
+                    names.join(", ")
+                )
+            })?],
+        Worksheets::Index(index) => vec![names.get(*index).cloned().ok_or_else(|| {
+            anyhow::anyhow!(
+                "Worksheet {} not found; the workbook has {}",
+                index + 1,
+                names.len()
+            )
+        })?],
+        Worksheets::All => names,
+    };
+
+    let mut sheets = Vec::new();
+
+    for name in chosen {
+        let range = workbook
+            .worksheet_range(&name)
+            .ok_or(calamine::Error::Msg("Sheet not found"))??;
+
+        match sheet(name, &range) {
+            Ok(sheet) if !sheet.headers.is_empty() || *worksheets != Worksheets::All => {
+                sheets.push(sheet);
+            }
+            Err(e) if *worksheets != Worksheets::All => return Err(e),
+            _ => {}
+        }
     }
 
-    Ok(records)
+    Ok(sheets)
+}
+
+/// Returns the rows of `range` as text, from its header row down.
+///
+/// # Errors
+///
+/// Will return `Err`, naming the worksheet, if it has rows but none of them
+/// can be the header.
+fn sheet(name: String, range: &Range<DataType>) -> anyhow::Result<Sheet> {
+    let width = |row: &[DataType]| row.iter().filter(|cell| !cell.is_empty()).count();
+    let widest = range.rows().map(width).max().unwrap_or_default();
+
+    let header = range.rows().position(|row| {
+        width(row) == widest
+            && row
+                .iter()
+                .all(|cell| cell.is_empty() || matches!(cell, DataType::String(_)))
+    });
+
+    let header = match header {
+        Some(header) => header,
+        None if widest == 0 => 0,
+        None => anyhow::bail!(
+            "Worksheet {name} has no header row: no row of text is as wide as its widest row"
+        ),
+    };
+
+    let mut rows = range
+        .rows()
+        .skip(header)
+        .filter(|row| width(row) > 0)
+        .map(|row| row.iter().map(ToString::to_string).collect::<Vec<_>>());
+
+    let headers = rows.next().unwrap_or_default();
+
+    Ok(Sheet {
+        name,
+        headers,
+        rows: rows.collect(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::ensure;
+    use calamine::Cell;
+
+    #[test]
+    fn test_header_row() -> anyhow::Result<()> {
+        let text = |s: &str| DataType::String(s.to_owned());
+        let range = Range::from_sparse(vec![
+            Cell::new((0, 0), text("Plant: Home")),
+            Cell::new((2, 0), text("Updated Time")),
+            Cell::new((2, 1), text("Production Power(W)")),
+            Cell::new((3, 0), text("2024/06/01 12:00")),
+            Cell::new((3, 1), DataType::Float(1250_f64)),
+            Cell::new((5, 0), text("2024/06/01 12:05")),
+            Cell::new((5, 1), DataType::Float(1300_f64)),
+        ]);
+
+        let sheet = sheet("Home".to_owned(), &range)?;
+
+        ensure!(sheet.headers == ["Updated Time", "Production Power(W)"]);
+        ensure!(sheet.rows == [["2024/06/01 12:00", "1250"], ["2024/06/01 12:05", "1300"]]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_no_header_row() -> anyhow::Result<()> {
+        let text = |s: &str| DataType::String(s.to_owned());
+        let range = Range::from_sparse(vec![
+            Cell::new((0, 0), text("Plant: Home")),
+            Cell::new((2, 0), text("Updated Time")),
+            Cell::new((3, 0), text("2024/06/01 12:00")),
+            Cell::new((3, 1), DataType::Float(1250_f64)),
+        ]);
+
+        // The data row is wider than the header, so no row qualifies.
+        let error = sheet("Home".to_owned(), &range).err();
+        ensure!(error.is_some_and(|e| e.to_string().contains("Worksheet Home")));
+
+        ensure!(sheet("Empty".to_owned(), &Range::default())?.headers.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_worksheets() -> anyhow::Result<()> {
+        ensure!("all".parse::<Worksheets>()? == Worksheets::All);
+        ensure!("2".parse::<Worksheets>()? == Worksheets::Index(1));
+        ensure!("Plant A".parse::<Worksheets>()? == Worksheets::Name("Plant A".to_owned()));
+        ensure!("0".parse::<Worksheets>().is_err());
+
+        Ok(())
+    }
 }
@@ -1,35 +1,107 @@
-use std::{ffi::OsStr, path::Path};
+use std::{
+    ffi::OsStr,
+    path::{Path, PathBuf},
+};
 
 pub mod csv;
 pub mod excel;
+mod sheet;
+
+pub use excel::Worksheets;
+pub use sheet::Sheet;
 
 pub fn parse_spreadsheets_from_folder<T, P>(path: P) -> anyhow::Result<Vec<T>>
 where
     P: AsRef<Path>,
     T: for<'de> serde::Deserialize<'de>,
 {
-    let directory_elements = std::fs::read_dir(path)?.collect::<Result<Vec<_>, _>>()?;
-
-    let files = directory_elements.into_iter().filter(|entry| {
-        let is_file = entry.file_type().map(|ft| !ft.is_dir()).unwrap_or(false);
-        let has_spreadsheet_extension = entry.path().extension().map_or(false, |ext| {
-            matches!(ext.to_str(), Some("csv" | "xlsx" | "xls"))
-        });
+    let records = parse_spreadsheet_files_from_folder(path)?
+        .into_iter()
+        .flat_map(|(_, records)| records)
+        .collect::<Vec<_>>();
 
-        is_file && has_spreadsheet_extension
-    });
+    Ok(records)
+}
 
-    let records = files
+/// Parses every spreadsheet in a folder, keeping the records of each file
+/// together and in the order they appear in the file.
+///
+/// Files are returned sorted by path.
+///
+/// # Errors
+///
+/// Will return `Err` if the folder cannot be read or if any spreadsheet in it
+/// fails to parse.
+pub fn parse_spreadsheet_files_from_folder<T, P>(path: P) -> anyhow::Result<Vec<(PathBuf, Vec<T>)>>
+where
+    P: AsRef<Path>,
+    T: for<'de> serde::Deserialize<'de>,
+{
+    spreadsheet_files(path)?
         .into_iter()
-        .map(|f| match f.path().extension().and_then(OsStr::to_str) {
-            Some("csv") => csv::read::<T, _>(f.path()),
-            Some("xlsx" | "xls") => excel::read::<T, _>(f.path()),
-            _ => Err(anyhow::anyhow!("Invalid file extension")),
+        .map(|path| {
+            let records = read::<T, _>(&path)?;
+
+            Ok((path, records))
         })
-        .collect::<Result<Vec<_>, _>>()?
+        .collect()
+}
+
+/// Lists the spreadsheets in a folder, sorted by path.
+///
+/// # Errors
+///
+/// Will return `Err` if the folder cannot be read.
+pub fn spreadsheet_files<P: AsRef<Path>>(path: P) -> anyhow::Result<Vec<PathBuf>> {
+    let directory_elements = std::fs::read_dir(path)?.collect::<Result<Vec<_>, _>>()?;
+
+    let mut files = directory_elements
         .into_iter()
-        .flatten()
+        .filter(|entry| {
+            let is_file = entry.file_type().map(|ft| !ft.is_dir()).unwrap_or(false);
+            let has_spreadsheet_extension = entry.path().extension().map_or(false, |ext| {
+                matches!(ext.to_str(), Some("csv" | "xlsx" | "xls"))
+            });
+
+            is_file && has_spreadsheet_extension
+        })
+        .map(|entry| entry.path())
         .collect::<Vec<_>>();
 
-    Ok(records)
+    files.sort();
+
+    Ok(files)
+}
+
+/// Reads a CSV or Excel file, chosen by its extension.
+///
+/// # Errors
+///
+/// Will return `Err` if the extension is not a spreadsheet one or if the file
+/// fails to parse.
+pub fn read<T, P>(path: P) -> anyhow::Result<Vec<T>>
+where
+    P: AsRef<Path>,
+    T: for<'de> serde::Deserialize<'de>,
+{
+    match path.as_ref().extension().and_then(OsStr::to_str) {
+        Some("csv") => csv::read::<T, _>(path),
+        Some("xlsx" | "xls") => excel::read::<T, _>(path),
+        _ => Err(anyhow::anyhow!("Invalid file extension")),
+    }
+}
+
+/// Reads a CSV or Excel file, chosen by its extension, as text: the CSV
+/// file as a single sheet, or the chosen `worksheets` of the Excel file.
+///
+/// # Errors
+///
+/// Will return `Err` if the extension is not a spreadsheet one or if the file
+/// cannot be read.
+pub fn read_sheets<P: AsRef<Path>>(path: P, worksheets: &Worksheets) -> anyhow::Result<Vec<Sheet>> {
+    match path.as_ref().extension().and_then(OsStr::to_str) {
+        Some("csv") => Ok(vec![csv::read_sheet(path)?]),
+        Some("xlsx" | "xls") => excel::read_sheets(path, worksheets),
+        _ => Err(anyhow::anyhow!("Invalid file extension")),
+    }
 }
@@ -0,0 +1,38 @@
+use anyhow::Context;
+use serde::Deserialize;
+
+/// A spreadsheet read as text: its header row and the cells of each row below
+/// it.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Sheet {
+    /// The worksheet name, or empty for formats without worksheets.
+    pub name: String,
+    pub headers: Vec<String>,
+    pub rows: Vec<Vec<String>>,
+}
+
+impl Sheet {
+    /// Deserializes each row, taking the headers as field names.
+    ///
+    /// Rows are numbered from the header row in errors, as in the file.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if any row does not deserialize into `T`.
+    pub fn deserialize<T>(&self) -> anyhow::Result<Vec<T>>
+    where
+        T: for<'de> Deserialize<'de>,
+    {
+        let headers = csv::StringRecord::from(self.headers.clone());
+
+        self.rows
+            .iter()
+            .enumerate()
+            .map(|(index, row)| {
+                csv::StringRecord::from(row.clone())
+                    .deserialize(Some(&headers))
+                    .with_context(|| format!("Failed to parse row {}", index + 2))
+            })
+            .collect()
+    }
+}
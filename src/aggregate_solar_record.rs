@@ -1,8 +1,11 @@
 use serde::Serialize;
 use tabled::Tabled;
 
-use crate::formatting::{euro_to_string, watt_hour_to_string};
+use crate::formatting::{
+    decimal_to_string, euro_to_string, percent_to_string, watt_hour_to_string,
+};
 use crate::solar_record::SolarRecord;
+use crate::tariff::Tariff;
 
 #[derive(Debug, Tabled, Serialize)]
 pub(crate) struct AggregateSolarRecord {
@@ -14,6 +17,8 @@ pub(crate) struct AggregateSolarRecord {
     cost: f64,
     #[tabled(rename = "Savings", display_with = "euro_to_string")]
     savings: f64,
+    #[tabled(rename = "Export Credit", display_with = "euro_to_string")]
+    export_credit: f64,
     #[tabled(rename = "Production", display_with = "watt_hour_to_string")]
     production: f64,
     #[tabled(rename = "Consumption", display_with = "watt_hour_to_string")]
@@ -22,6 +27,26 @@ pub(crate) struct AggregateSolarRecord {
     purchased: f64,
     #[tabled(rename = "Feed In", display_with = "watt_hour_to_string")]
     feed_in: f64,
+    /// Share of production used on site.
+    #[tabled(rename = "Self-Consumption", display_with = "percent_to_string")]
+    self_consumption: f64,
+    /// Share of consumption covered on site.
+    #[tabled(rename = "Self-Sufficiency", display_with = "percent_to_string")]
+    self_sufficiency: f64,
+    #[tabled(rename = "Charged", display_with = "watt_hour_to_string")]
+    battery_charge: f64,
+    #[tabled(rename = "Discharged", display_with = "watt_hour_to_string")]
+    battery_discharge: f64,
+    /// Equivalent full cycles, from the total rise in state of charge.
+    #[tabled(rename = "Cycles", display_with = "decimal_to_string")]
+    cycles: f64,
+    #[tabled(rename = "Mean SoC", display_with = "percent_to_string")]
+    mean_soc: f64,
+    #[tabled(rename = "Min SoC", display_with = "percent_to_string")]
+    min_soc: f64,
+    /// Energy discharged as a percentage of energy charged.
+    #[tabled(rename = "Efficiency", display_with = "percent_to_string")]
+    efficiency: f64,
 }
 
 macro_rules! getters {
@@ -37,12 +62,40 @@ macro_rules! getters {
 
 impl AggregateSolarRecord {
     #[must_use]
-    pub fn new(records: &[SolarRecord], key: &str) -> Self {
-        let cost = records.iter().map(SolarRecord::cost).sum::<f64>();
-        let old_cost = records.iter().map(SolarRecord::old_cost).sum::<f64>();
+    pub fn new(records: &[SolarRecord], key: &str, tariff: &Tariff) -> Self {
+        let cost = records.iter().map(|r| r.cost(tariff)).sum::<f64>();
+        let old_cost = records.iter().map(|r| r.old_cost(tariff)).sum::<f64>();
+        let export_credit = records.iter().map(|r| r.export_credit(tariff)).sum::<f64>();
 
         let savings = old_cost - cost;
 
+        let cycles = records
+            .windows(2)
+            .map(|pair| f64::from(pair[1].soc().saturating_sub(pair[0].soc())))
+            .sum::<f64>()
+            / 100_f64;
+
+        let minutes = records
+            .iter()
+            .map(|r| r.duration().num_minutes() as f64)
+            .sum::<f64>();
+        let mean_soc = records
+            .iter()
+            .map(|r| f64::from(r.soc()) * r.duration().num_minutes() as f64)
+            .sum::<f64>()
+            / minutes.max(1_f64);
+        let min_soc = records
+            .iter()
+            .map(SolarRecord::soc)
+            .min()
+            .unwrap_or_default();
+
+        let battery_charge = records.iter().map(SolarRecord::battery_charge).sum::<f64>();
+        let battery_discharge = records
+            .iter()
+            .map(SolarRecord::battery_discharge)
+            .sum::<f64>();
+
         macro_rules! construct {
             ($record:expr, $($field:ident),*) => {
                 $(let $field = $record.iter().map(|r| r.$field()).sum::<f64>();)*
@@ -52,21 +105,169 @@ impl AggregateSolarRecord {
                     cost,
                     old_cost,
                     savings,
+                    export_credit,
+                    battery_charge,
+                    battery_discharge,
+                    cycles,
+                    mean_soc,
+                    min_soc: f64::from(min_soc),
+                    efficiency: 0_f64,
+                    self_consumption: 0_f64,
+                    self_sufficiency: 0_f64,
                     $($field,)*
                 }
+                .with_ratios()
             }
         }
 
         construct!(records, production, consumption, purchased, feed_in);
     }
 
-    getters!(
-        old_cost,
-        cost,
-        savings,
-        production,
-        consumption,
-        purchased,
-        feed_in
-    );
+    /// Averages each column of `records`. The ratios are worked out from the
+    /// mean flows, so that each record counts by its energy, and the minimum
+    /// state of charge is the lowest of all.
+    #[must_use]
+    pub fn mean(records: &[Self], key: &str) -> Self {
+        let count = records.len().max(1) as f64;
+        let min_soc = records
+            .iter()
+            .map(|r| r.min_soc)
+            .reduce(f64::min)
+            .unwrap_or_default();
+
+        macro_rules! construct {
+            ($($field:ident),*) => {
+                return Self {
+                    key: key.to_owned(),
+                    self_consumption: 0_f64,
+                    self_sufficiency: 0_f64,
+                    efficiency: 0_f64,
+                    min_soc,
+                    $($field: records.iter().map(|r| r.$field).sum::<f64>() / count,)*
+                }
+                .with_ratios()
+            }
+        }
+
+        construct!(
+            old_cost,
+            cost,
+            savings,
+            export_credit,
+            production,
+            consumption,
+            purchased,
+            feed_in,
+            battery_charge,
+            battery_discharge,
+            cycles,
+            mean_soc
+        );
+    }
+
+    /// Adds charges billed once per billing period, such as the PSO levy and
+    /// bill credits, to both costs, leaving the savings as they are.
+    #[must_use]
+    pub fn with_period_charges(self, charges: f64) -> Self {
+        Self {
+            old_cost: self.old_cost + charges,
+            cost: self.cost + charges,
+            ..self
+        }
+    }
+
+    /// Fills in the self-consumption, self-sufficiency and efficiency ratios
+    /// from the energy flows.
+    #[must_use]
+    fn with_ratios(self) -> Self {
+        Self {
+            self_consumption: percentage(self.production - self.feed_in, self.production),
+            self_sufficiency: percentage(self.consumption - self.purchased, self.consumption),
+            efficiency: percentage(self.battery_discharge, self.battery_charge),
+            ..self
+        }
+    }
+
+    #[must_use]
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    getters!(cost, savings, feed_in);
+}
+
+/// Returns `part` as a percentage of `whole`, clamped to 0–100%, or zero if
+/// `whole` is zero.
+fn percentage(part: f64, whole: f64) -> f64 {
+    if whole > 0_f64 {
+        (part / whole * 100_f64).clamp(0_f64, 100_f64)
+    } else {
+        0_f64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::ensure;
+
+    #[test]
+    fn test_battery_metrics() -> anyhow::Result<()> {
+        let flows = [(2000, 20), (2000, 60), (-1500, 40), (-1500, 20)];
+        let records = SolarRecord::hourly("2024-06-01 10:00", flows.len(), |index| {
+            let (battery, soc) = flows[index];
+            (0, 0, 0, battery, soc)
+        })?;
+
+        let record = AggregateSolarRecord::new(&records, "2024-06-01", &Tariff::default());
+
+        ensure!((record.battery_charge - 4000_f64).abs() < f64::EPSILON);
+        ensure!((record.battery_discharge - 3000_f64).abs() < f64::EPSILON);
+        ensure!((record.efficiency - 75_f64).abs() < f64::EPSILON);
+        ensure!((record.cycles - 0.4).abs() < f64::EPSILON);
+        ensure!((record.mean_soc - 35_f64).abs() < f64::EPSILON);
+        ensure!((record.min_soc - 20_f64).abs() < f64::EPSILON);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_ratios() -> anyhow::Result<()> {
+        let flows = [(4000, 1000, 3000), (0, 1000, -1000)];
+        let records = SolarRecord::hourly("2024-06-01 10:00", flows.len(), |index| {
+            let (production, consumption, grid) = flows[index];
+            (production, consumption, grid, 0, 0)
+        })?;
+
+        let record = AggregateSolarRecord::new(&records, "2024-06-01", &Tariff::default());
+
+        ensure!((record.self_consumption - 25_f64).abs() < f64::EPSILON);
+        ensure!((record.self_sufficiency - 50_f64).abs() < f64::EPSILON);
+
+        let mean = AggregateSolarRecord::mean(&[record], "Mean");
+        ensure!((mean.self_consumption - 25_f64).abs() < f64::EPSILON);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_mean_battery_metrics() -> anyhow::Result<()> {
+        let flows = [(2000, 20), (2000, 60), (-1500, 40), (-1500, 30)];
+        let records = SolarRecord::hourly("2024-06-01 10:00", flows.len(), |index| {
+            let (battery, soc) = flows[index];
+            (0, 0, 0, battery, soc)
+        })?;
+
+        // One period only charges and the other only discharges.
+        let periods = records
+            .chunks(2)
+            .map(|records| AggregateSolarRecord::new(records, "2024-06-01", &Tariff::default()))
+            .collect::<Vec<_>>();
+        let mean = AggregateSolarRecord::mean(&periods, "Mean");
+
+        ensure!((mean.efficiency - 75_f64).abs() < f64::EPSILON);
+        ensure!((mean.min_soc - 20_f64).abs() < f64::EPSILON);
+
+        Ok(())
+    }
 }
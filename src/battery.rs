@@ -0,0 +1,449 @@
+use core::fmt::{self, Display, Formatter};
+
+use clap::ValueEnum;
+
+use crate::{
+    formatting::{euro_to_string, percent_to_string, watt_hour_to_string},
+    solar_data::SolarData,
+    solar_record::SolarRecord,
+    tariff::Tariff,
+};
+
+/// How a battery decides when to charge and discharge.
+#[non_exhaustive]
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Dispatch {
+    /// Charge from surplus production and discharge to cover consumption.
+    #[default]
+    SelfConsumption,
+    /// As self-consumption, but charge from the grid during the cheapest
+    /// band of the day and keep the charge for later.
+    GridCharge,
+    /// Charge from surplus production, keeping the charge for the dearest
+    /// band of the day.
+    HoldForPeak,
+    /// Charge from surplus production and discharge only to keep grid import
+    /// down to the battery's peak limit.
+    PeakShaving,
+}
+
+impl Dispatch {
+    #[must_use]
+    #[inline]
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Self::SelfConsumption => "Self-Consumption",
+            Self::GridCharge => "Grid Charge",
+            Self::HoldForPeak => "Hold for Peak",
+            Self::PeakShaving => "Peak Shaving",
+        }
+    }
+
+    /// Returns the power, in W, the battery should charge at during `record`,
+    /// or discharge at if negative, before the battery's limits apply.
+    /// Peak shaving lets the grid supply up to `peak_limit` W.
+    #[must_use]
+    fn target(self, record: &SolarRecord, tariff: &Tariff, peak_limit: f64) -> f64 {
+        let surplus = record.surplus();
+
+        let rate = record.rate(tariff);
+        let slot = rate.slot(record.date_time());
+        let unit_rate = rate.band(slot).map_or(0_f64, |band| band.rate);
+        let (cheapest, dearest) = rate.daily_range(slot);
+
+        let is_cheapest = unit_rate <= cheapest + f64::EPSILON && cheapest < dearest;
+        let is_dearest = unit_rate >= dearest - f64::EPSILON;
+
+        match self {
+            Self::GridCharge if is_cheapest => f64::INFINITY,
+            Self::HoldForPeak if surplus <= 0_f64 && !is_dearest => 0_f64,
+            Self::PeakShaving if surplus <= 0_f64 => (surplus + peak_limit).min(0_f64),
+            Self::SelfConsumption | Self::GridCharge | Self::HoldForPeak | Self::PeakShaving => {
+                surplus
+            }
+        }
+    }
+}
+
+/// A virtual battery to replay recorded data against.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Battery {
+    /// Usable capacity, in Wh.
+    capacity: f64,
+    /// Maximum charging power, in W.
+    max_charge: f64,
+    /// Maximum discharging power, in W.
+    max_discharge: f64,
+    /// Round-trip efficiency, as a fraction.
+    efficiency: f64,
+    /// Minimum state of charge, as a percentage.
+    min_soc: f64,
+    /// Grid import, in W, above which peak shaving discharges.
+    peak_limit: f64,
+}
+
+impl Battery {
+    /// Creates a battery of `capacity` kWh that charges at up to `max_charge`
+    /// kW and discharges at up to `max_discharge` kW, with a round-trip
+    /// `efficiency` and `min_soc` given as percentages.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the capacity or either power is not greater than
+    /// zero, or if either percentage is outside 0-100.
+    #[inline]
+    pub fn new(
+        capacity: f64,
+        max_charge: f64,
+        max_discharge: f64,
+        efficiency: f64,
+        min_soc: f64,
+    ) -> anyhow::Result<Self> {
+        for (name, value) in [
+            ("Capacity", capacity),
+            ("Maximum charge", max_charge),
+            ("Maximum discharge", max_discharge),
+        ] {
+            anyhow::ensure!(
+                value > 0_f64,
+                "{name} must be greater than zero, not {value}"
+            );
+        }
+
+        for (name, value) in [("Efficiency", efficiency), ("Minimum SoC", min_soc)] {
+            anyhow::ensure!(
+                (0_f64..=100_f64).contains(&value),
+                "{name} must be a percentage from 0 to 100, not {value}"
+            );
+        }
+
+        Ok(Self {
+            capacity: capacity * 1000_f64,
+            max_charge: max_charge * 1000_f64,
+            max_discharge: max_discharge * 1000_f64,
+            efficiency: efficiency / 100_f64,
+            min_soc,
+            peak_limit: 0_f64,
+        })
+    }
+
+    /// Sets the grid import, in kW, above which peak shaving discharges the
+    /// battery.
+    #[must_use]
+    #[inline]
+    pub fn with_peak_limit(self, peak_limit: f64) -> Self {
+        Self {
+            peak_limit: peak_limit.max(0_f64) * 1000_f64,
+            ..self
+        }
+    }
+
+    /// Energy held back by the minimum state of charge, in Wh.
+    #[must_use]
+    fn reserve(&self) -> f64 {
+        self.capacity * self.min_soc / 100_f64
+    }
+
+    /// Replays `records` with the battery installed in place of any recorded
+    /// one, dispatched by `dispatch` against `tariff`. Losses are split evenly
+    /// between charging and discharging, and the battery starts at its
+    /// minimum state of charge.
+    #[must_use]
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    pub(crate) fn simulate(
+        &self,
+        records: &[SolarRecord],
+        tariff: &Tariff,
+        dispatch: Dispatch,
+    ) -> Vec<SolarRecord> {
+        let loss = self.efficiency.sqrt();
+        let mut stored = self.reserve();
+
+        records
+            .iter()
+            .map(|record| {
+                let hours = record.duration().num_minutes() as f64 / 60_f64;
+                let target = dispatch.target(record, tariff, self.peak_limit);
+
+                let power = if hours <= 0_f64 || loss <= 0_f64 {
+                    0_f64
+                } else if target > 0_f64 {
+                    let room = (self.capacity - stored) / (hours * loss);
+                    target.min(self.max_charge).min(room).max(0_f64)
+                } else {
+                    let available = (stored - self.reserve()) * loss / hours;
+                    -(-target).min(self.max_discharge).min(available).max(0_f64)
+                };
+
+                stored += if power > 0_f64 {
+                    power * hours * loss
+                } else if power < 0_f64 {
+                    power * hours / loss
+                } else {
+                    0_f64
+                };
+
+                let soc = if self.capacity > 0_f64 {
+                    (stored / self.capacity * 100_f64)
+                        .round()
+                        .clamp(0_f64, 100_f64) as u8
+                } else {
+                    0
+                };
+
+                record.with_battery(power.round() as i32, soc)
+            })
+            .collect()
+    }
+}
+
+impl Display for Battery {
+    #[inline]
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}, {:.2}kW charge, {:.2}kW discharge, {} round trip, {} min SoC",
+            watt_hour_to_string(&self.capacity),
+            self.max_charge / 1000_f64,
+            self.max_discharge / 1000_f64,
+            percent_to_string(&(self.efficiency * 100_f64)),
+            percent_to_string(&self.min_soc),
+        )
+    }
+}
+
+/// The recorded data replayed without a battery and with a virtual one.
+#[derive(Debug)]
+pub struct BatterySimulation {
+    battery: Battery,
+    dispatch: Dispatch,
+    cost: f64,
+    baseline: SolarData,
+    simulated: SolarData,
+}
+
+impl BatterySimulation {
+    #[must_use]
+    pub(crate) fn new(
+        battery: Battery,
+        dispatch: Dispatch,
+        cost: f64,
+        baseline: SolarData,
+        simulated: SolarData,
+    ) -> Self {
+        Self {
+            battery,
+            dispatch,
+            cost,
+            baseline,
+            simulated,
+        }
+    }
+
+    /// Savings the battery adds over the recorded period.
+    #[must_use]
+    #[inline]
+    pub fn extra_savings(&self) -> f64 {
+        self.simulated.savings() - self.baseline.savings()
+    }
+
+    /// Savings the battery adds per year, extrapolated from the recorded days.
+    #[must_use]
+    #[inline]
+    pub fn annual_extra_savings(&self) -> f64 {
+        self.extra_savings() / self.baseline.days().max(1) as f64 * 365_f64
+    }
+
+    /// Years until the battery pays for itself, or `None` if it never does.
+    #[must_use]
+    #[inline]
+    pub fn payback_years(&self) -> Option<f64> {
+        let annual = self.annual_extra_savings();
+        (annual > 0_f64).then(|| self.cost / annual)
+    }
+}
+
+impl Display for BatterySimulation {
+    #[inline]
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let payback = self
+            .payback_years()
+            .map_or_else(|| "never".to_owned(), |years| format!("{years:.1} years"));
+
+        write!(
+            f,
+            "{}\nBattery: {}\nDispatch: {}\nBattery Cost: {}\nExtra Savings: {} over {} days ({} per year)\nBattery Payback: {payback}\n",
+            self.simulated,
+            self.battery,
+            self.dispatch.name(),
+            euro_to_string(&self.cost),
+            euro_to_string(&self.extra_savings()),
+            self.baseline.days(),
+            euro_to_string(&self.annual_extra_savings()),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::{ensure, Context};
+    use chrono::{Duration, TimeZone};
+    use chrono_tz::Europe::Dublin;
+
+    fn records(flows: &[(u32, u32, u32)]) -> anyhow::Result<Vec<SolarRecord>> {
+        flows
+            .iter()
+            .map(|&(hour, production, consumption)| {
+                let date_time = Dublin
+                    .with_ymd_and_hms(2024, 6, 1, hour, 0, 0)
+                    .single()
+                    .context("Failed to create DateTime<Tz> value")?;
+
+                Ok(SolarRecord::new(
+                    date_time,
+                    Duration::hours(1),
+                    production,
+                    consumption,
+                    0,
+                    0,
+                    0,
+                ))
+            })
+            .collect()
+    }
+
+    fn night_rate_tariff() -> anyhow::Result<Tariff> {
+        Tariff::from_toml(
+            r#"
+            [[versions]]
+            name = "Night Rate"
+            start = "2024-01-01"
+            standing_charge = 0.5
+            export = 0.2
+
+            [[versions.bands]]
+            name = "Night"
+            hours = [2, 3]
+            rate = 0.1
+
+            [[versions.bands]]
+            name = "Peak"
+            hours = [17, 18]
+            rate = 0.5
+
+            [[versions.bands]]
+            name = "Day"
+            hours = [0, 1, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 19, 20, 21, 22, 23]
+            rate = 0.3
+        "#,
+        )
+    }
+
+    #[test]
+    fn test_simulate() -> anyhow::Result<()> {
+        let battery = Battery::new(5_f64, 2_f64, 1_f64, 81_f64, 10_f64)?;
+        let records = records(&[
+            (10, 3000, 500),
+            (11, 3000, 0),
+            (12, 3000, 0),
+            (13, 0, 2000),
+            (14, 0, 2000),
+        ])?;
+
+        let simulated = battery.simulate(&records, &Tariff::default(), Dispatch::SelfConsumption);
+
+        // Charging is capped at 2kW, storing 1.8kWh per hour on top of the
+        // 0.5kWh reserve until the 5kWh capacity is reached.
+        ensure!((simulated[0].battery_charge() - 2000_f64).abs() < f64::EPSILON);
+        ensure!((simulated[0].feed_in() - 500_f64).abs() < f64::EPSILON);
+        ensure!(simulated[1].soc() == 82);
+        ensure!((simulated[2].battery_charge() - 1000_f64).abs() < f64::EPSILON);
+        ensure!(simulated[2].soc() == 100);
+
+        // Discharging is capped at 1kW, the rest is bought from the grid.
+        ensure!((simulated[3].battery_discharge() - 1000_f64).abs() < f64::EPSILON);
+        ensure!((simulated[3].purchased() - 1000_f64).abs() < f64::EPSILON);
+        ensure!(simulated[4].soc() == 56);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_invalid_battery() -> anyhow::Result<()> {
+        ensure!(Battery::new(0_f64, 2.5, 2.5, 90_f64, 10_f64).is_err());
+        ensure!(Battery::new(5_f64, -1_f64, 2.5, 90_f64, 10_f64).is_err());
+        ensure!(Battery::new(5_f64, 2.5, 2.5, 120_f64, 10_f64).is_err());
+        ensure!(Battery::new(5_f64, 2.5, 2.5, 90_f64, -10_f64).is_err());
+        ensure!(Battery::new(5_f64, 2.5, 2.5, 100_f64, 0_f64).is_ok());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_min_soc() -> anyhow::Result<()> {
+        let battery = Battery::new(5_f64, 5_f64, 5_f64, 100_f64, 20_f64)?;
+        let records = records(&[(10, 2000, 0), (11, 0, 5000), (12, 0, 5000)])?;
+
+        let simulated = battery.simulate(&records, &Tariff::default(), Dispatch::SelfConsumption);
+
+        ensure!((simulated[1].battery_discharge() - 2000_f64).abs() < f64::EPSILON);
+        ensure!((simulated[1].purchased() - 3000_f64).abs() < f64::EPSILON);
+        ensure!(simulated[1].soc() == 20);
+        ensure!(simulated[2].battery_discharge().abs() < f64::EPSILON);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_grid_charge() -> anyhow::Result<()> {
+        let battery = Battery::new(5_f64, 2.5, 2.5, 100_f64, 0_f64)?;
+        let records = records(&[(2, 0, 500), (12, 3000, 500), (13, 0, 1000), (17, 0, 2000)])?;
+
+        let simulated = battery.simulate(&records, &night_rate_tariff()?, Dispatch::GridCharge);
+
+        ensure!((simulated[0].battery_charge() - 2500_f64).abs() < f64::EPSILON);
+        ensure!((simulated[0].purchased() - 3000_f64).abs() < f64::EPSILON);
+        ensure!(simulated[1].soc() == 100);
+        ensure!((simulated[2].battery_discharge() - 1000_f64).abs() < f64::EPSILON);
+        ensure!((simulated[3].battery_discharge() - 2000_f64).abs() < f64::EPSILON);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_hold_for_peak() -> anyhow::Result<()> {
+        let battery = Battery::new(5_f64, 2.5, 2.5, 100_f64, 0_f64)?;
+        let records = records(&[(2, 0, 500), (12, 3000, 500), (13, 0, 1000), (17, 0, 2000)])?;
+
+        let simulated = battery.simulate(&records, &night_rate_tariff()?, Dispatch::HoldForPeak);
+
+        ensure!((simulated[0].purchased() - 500_f64).abs() < f64::EPSILON);
+        ensure!((simulated[1].battery_charge() - 2500_f64).abs() < f64::EPSILON);
+        ensure!((simulated[2].purchased() - 1000_f64).abs() < f64::EPSILON);
+        ensure!((simulated[3].battery_discharge() - 2000_f64).abs() < f64::EPSILON);
+        ensure!(simulated[3].purchased().abs() < f64::EPSILON);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_peak_shaving() -> anyhow::Result<()> {
+        let battery = Battery::new(5_f64, 2.5, 2.5, 100_f64, 0_f64)?.with_peak_limit(1_f64);
+        let records = records(&[(12, 3000, 500), (13, 0, 800), (18, 0, 3000), (19, 0, 5000)])?;
+
+        let simulated = battery.simulate(&records, &Tariff::default(), Dispatch::PeakShaving);
+
+        // Import below the 1kW limit is left to the grid, above it the
+        // battery covers the excess until it runs out.
+        ensure!((simulated[0].battery_charge() - 2500_f64).abs() < f64::EPSILON);
+        ensure!(simulated[1].battery_discharge().abs() < f64::EPSILON);
+        ensure!((simulated[1].purchased() - 800_f64).abs() < f64::EPSILON);
+        ensure!((simulated[2].battery_discharge() - 2000_f64).abs() < f64::EPSILON);
+        ensure!((simulated[2].purchased() - 1000_f64).abs() < f64::EPSILON);
+        ensure!((simulated[3].battery_discharge() - 500_f64).abs() < f64::EPSILON);
+        ensure!((simulated[3].purchased() - 4500_f64).abs() < f64::EPSILON);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,328 @@
+use core::fmt::{self, Display, Formatter};
+use std::path::{Path, PathBuf};
+
+use chrono::{NaiveDate, NaiveDateTime};
+use parsers::{csv, Worksheets};
+use serde::Serialize;
+use tabled::{settings::Style, Table, Tabled};
+
+use crate::{export::Exports, solarman_record::SolarmanRecord};
+
+/// Something implausible about a single Solarman reading.
+#[non_exhaustive]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Issue {
+    /// Production and import do not match consumption, export and battery
+    /// charge; the residual is in W.
+    Imbalance(i32),
+    /// Production below zero, in W.
+    NegativeProduction(i32),
+    /// Consumption below zero, in W.
+    NegativeConsumption(i32),
+    /// The reading is identical to this many readings before it.
+    Frozen(usize),
+    /// State of charge above 100%.
+    SocOutOfRange(u8),
+    /// State of charge moved this many percentage points since the previous
+    /// reading.
+    SocJump(i16),
+}
+
+impl Display for Issue {
+    #[inline]
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match *self {
+            Self::Imbalance(residual) => write!(f, "energy balance off by {residual}W"),
+            Self::NegativeProduction(power) => write!(f, "negative production of {power}W"),
+            Self::NegativeConsumption(power) => write!(f, "negative consumption of {power}W"),
+            Self::Frozen(count) => write!(f, "reading unchanged for {count} samples"),
+            Self::SocOutOfRange(soc) => write!(f, "state of charge of {soc}%"),
+            Self::SocJump(jump) => write!(f, "state of charge jumped {jump:+} points"),
+        }
+    }
+}
+
+/// Thresholds for the balance and plausibility rules.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rules {
+    /// Largest allowed energy balance residual, in W.
+    tolerance: u32,
+    /// Number of identical consecutive readings after which values are
+    /// considered frozen.
+    frozen: usize,
+    /// Largest allowed change in state of charge between readings, in
+    /// percentage points.
+    soc_jump: u8,
+}
+
+impl Default for Rules {
+    #[inline]
+    fn default() -> Self {
+        Self {
+            tolerance: 100,
+            frozen: 6,
+            soc_jump: 20,
+        }
+    }
+}
+
+impl Rules {
+    #[must_use]
+    #[inline]
+    pub fn new(tolerance: u32, frozen: usize, soc_jump: u8) -> Self {
+        Self {
+            tolerance,
+            frozen,
+            soc_jump,
+        }
+    }
+
+    /// Runs the rules over the readings of one export, in file order,
+    /// returning the index and issues of each offending reading.
+    #[must_use]
+    pub(crate) fn check(&self, records: &[SolarmanRecord]) -> Vec<(usize, Vec<Issue>)> {
+        let mut unchanged = 0;
+
+        records
+            .iter()
+            .enumerate()
+            .filter_map(|(index, record)| {
+                let previous = index.checked_sub(1).and_then(|i| records.get(i));
+                let mut issues = Vec::new();
+
+                let residual = i64::from(record.production)
+                    - i64::from(record.consumption)
+                    - i64::from(record.grid)
+                    - i64::from(record.battery);
+                if residual.unsigned_abs() > u64::from(self.tolerance) {
+                    issues.push(Issue::Imbalance(
+                        i32::try_from(residual).unwrap_or(i32::MAX),
+                    ));
+                }
+
+                if record.production < 0 {
+                    issues.push(Issue::NegativeProduction(record.production));
+                }
+
+                if record.consumption < 0 {
+                    issues.push(Issue::NegativeConsumption(record.consumption));
+                }
+
+                unchanged = match previous {
+                    Some(previous) if same_reading(previous, record) => unchanged + 1,
+                    _ => 0,
+                };
+                let idle = record.production == 0 && record.consumption == 0;
+                if self.frozen > 0 && unchanged >= self.frozen && !idle {
+                    issues.push(Issue::Frozen(unchanged));
+                }
+
+                if record.soc > 100 {
+                    issues.push(Issue::SocOutOfRange(record.soc));
+                }
+
+                if let Some(previous) = previous {
+                    let jump = i16::from(record.soc) - i16::from(previous.soc);
+                    if jump.unsigned_abs() > u16::from(self.soc_jump) {
+                        issues.push(Issue::SocJump(jump));
+                    }
+                }
+
+                (!issues.is_empty()).then_some((index, issues))
+            })
+            .collect()
+    }
+}
+
+/// Whether two readings report exactly the same values.
+fn same_reading(a: &SolarmanRecord, b: &SolarmanRecord) -> bool {
+    (a.production, a.consumption, a.grid, a.battery, a.soc)
+        == (b.production, b.consumption, b.grid, b.battery, b.soc)
+}
+
+/// One offending reading.
+#[derive(Debug, Tabled, Serialize)]
+pub struct Finding {
+    #[tabled(rename = "File")]
+    file: String,
+    #[tabled(rename = "Time")]
+    time: NaiveDateTime,
+    #[tabled(rename = "Issues")]
+    issues: String,
+}
+
+/// The readings of each export that break the balance and plausibility rules.
+#[derive(Debug)]
+pub struct CheckReport {
+    /// Each file with its number of readings and of offending readings.
+    files: Vec<(String, usize, usize)>,
+    findings: Vec<Finding>,
+}
+
+impl CheckReport {
+    /// Runs `rules` over the readings on or after `from` and on or before
+    /// `to` of every power export in the folder at `path`, reading the chosen
+    /// `worksheets` of Excel files. Energy reports have no readings to check.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the folder or any export in it cannot be read.
+    #[inline]
+    pub fn from_folder<P: AsRef<Path>>(
+        path: P,
+        rules: Rules,
+        from: Option<NaiveDate>,
+        to: Option<NaiveDate>,
+        worksheets: &Worksheets,
+    ) -> anyhow::Result<Self> {
+        let mut files = Exports::from_folder(path, worksheets)?.power;
+
+        for (_, records) in &mut files {
+            records.retain(|record| {
+                let date = record.time.date();
+                from.is_none_or(|from| date >= from) && to.is_none_or(|to| date <= to)
+            });
+        }
+
+        Ok(Self::new(&files, rules))
+    }
+
+    #[must_use]
+    pub(crate) fn new(files: &[(PathBuf, Vec<SolarmanRecord>)], rules: Rules) -> Self {
+        let mut findings = Vec::new();
+
+        let files = files
+            .iter()
+            .map(|(path, records)| {
+                let file = path.file_name().map_or_else(
+                    || path.display().to_string(),
+                    |name| name.to_string_lossy().into_owned(),
+                );
+                let offending = rules.check(records);
+
+                for (index, issues) in &offending {
+                    if let Some(record) = records.get(*index) {
+                        findings.push(Finding {
+                            file: file.clone(),
+                            time: record.time,
+                            issues: issues
+                                .iter()
+                                .map(ToString::to_string)
+                                .collect::<Vec<_>>()
+                                .join(", "),
+                        });
+                    }
+                }
+
+                (file, records.len(), offending.len())
+            })
+            .collect();
+
+        Self { files, findings }
+    }
+
+    /// Returns the number of offending readings.
+    #[must_use]
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.findings.len()
+    }
+
+    #[must_use]
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.findings.is_empty()
+    }
+
+    /// Writes the offending readings to a CSV file.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the file cannot be written.
+    #[inline]
+    pub fn write<P: AsRef<Path>>(&self, path: P) -> anyhow::Result<()> {
+        csv::write(path, &self.findings)
+    }
+}
+
+impl Display for CheckReport {
+    #[inline]
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        if !self.findings.is_empty() {
+            let mut table = Table::new(&self.findings);
+            table.with(Style::rounded());
+            writeln!(f, "{table}")?;
+        }
+
+        for (file, records, offending) in &self.files {
+            writeln!(f, "{file}: {offending} of {records} readings flagged")?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::ensure;
+
+    fn reading(
+        minute: u32,
+        production: i32,
+        consumption: i32,
+        grid: i32,
+        soc: u8,
+    ) -> anyhow::Result<SolarmanRecord> {
+        Ok(SolarmanRecord {
+            time: NaiveDateTime::parse_from_str(
+                &format!("2024/06/01 12:{minute:02}"),
+                "%Y/%m/%d %H:%M",
+            )?,
+            production,
+            consumption,
+            grid,
+            battery: 0,
+            soc,
+            daily_production: None,
+        })
+    }
+
+    #[test]
+    fn test_check() -> anyhow::Result<()> {
+        let records = [
+            reading(0, 1000, 400, 600, 50)?,
+            reading(5, 1000, 400, 100, 50)?,
+            reading(10, -20, 400, -420, 50)?,
+            reading(15, 0, 400, -400, 90)?,
+            reading(20, 0, 400, -400, 101)?,
+        ];
+
+        let offending = Rules::default().check(&records);
+
+        ensure!(
+            offending
+                == [
+                    (1, vec![Issue::Imbalance(500)]),
+                    (2, vec![Issue::NegativeProduction(-20)]),
+                    (3, vec![Issue::SocJump(40)]),
+                    (4, vec![Issue::SocOutOfRange(101)]),
+                ]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_frozen() -> anyhow::Result<()> {
+        let records = (0..5)
+            .map(|minute| reading(minute * 5, 1000, 400, 600, 50))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+
+        let offending = Rules::new(100, 3, 20).check(&records);
+
+        ensure!(offending == [(3, vec![Issue::Frozen(3)]), (4, vec![Issue::Frozen(4)])]);
+
+        Ok(())
+    }
+}
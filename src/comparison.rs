@@ -0,0 +1,183 @@
+use core::fmt::{self, Display, Formatter};
+
+use tabled::{builder::Builder, settings::Style};
+
+use crate::{aggregate_solar_record::AggregateSolarRecord, formatting::euro_to_string};
+
+/// Marks the cheapest cost in each row of a comparison.
+const CHEAPEST: &str = "*";
+
+/// The cost of the same history under several tariffs, ranked from cheapest
+/// to most expensive.
+#[derive(Debug)]
+pub struct Comparison {
+    /// The tariff names and their costs per period, cheapest first.
+    tariffs: Vec<(String, Vec<AggregateSolarRecord>)>,
+    limit: usize,
+    /// What is being compared, used as the ranking table's column header.
+    label: &'static str,
+}
+
+impl Comparison {
+    #[must_use]
+    pub(crate) fn new(mut tariffs: Vec<(String, Vec<AggregateSolarRecord>)>, limit: usize) -> Self {
+        tariffs.sort_by(|(_, a), (_, b)| total_cost(a).total_cmp(&total_cost(b)));
+
+        Self {
+            tariffs,
+            limit,
+            label: "Tariff",
+        }
+    }
+
+    /// Sets what is being compared, e.g. "Strategy".
+    #[must_use]
+    pub(crate) fn labelled(mut self, label: &'static str) -> Self {
+        self.label = label;
+        self
+    }
+
+    /// Returns the tariff names, cheapest first.
+    #[must_use]
+    #[inline]
+    pub fn ranking(&self) -> Vec<&str> {
+        self.tariffs.iter().map(|(name, _)| name.as_str()).collect()
+    }
+
+    /// Returns the total cost under each tariff, cheapest first.
+    #[must_use]
+    #[inline]
+    pub fn totals(&self) -> Vec<f64> {
+        self.tariffs
+            .iter()
+            .map(|(_, records)| total_cost(records))
+            .collect()
+    }
+
+    /// Formats a row of costs, marking the cheapest.
+    fn row(label: &str, costs: &[f64]) -> Vec<String> {
+        let cheapest = costs.iter().copied().fold(f64::INFINITY, f64::min);
+
+        core::iter::once(label.to_owned())
+            .chain(costs.iter().map(|cost| {
+                if (cost - cheapest).abs() < f64::EPSILON {
+                    format!("{CHEAPEST}{}", euro_to_string(cost))
+                } else {
+                    euro_to_string(cost)
+                }
+            }))
+            .collect()
+    }
+}
+
+fn total_cost(records: &[AggregateSolarRecord]) -> f64 {
+    records.iter().map(AggregateSolarRecord::cost).sum()
+}
+
+impl Display for Comparison {
+    #[inline]
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let Some((_, first)) = self.tariffs.first() else {
+            return Ok(());
+        };
+
+        let mut periods = Builder::default();
+        periods.set_header(
+            core::iter::once("Date".to_owned())
+                .chain(self.tariffs.iter().map(|(name, _)| name.clone())),
+        );
+
+        let shown = first.len().saturating_sub(self.limit);
+
+        for (index, record) in first.iter().enumerate().skip(shown) {
+            let costs = self
+                .tariffs
+                .iter()
+                .map(|(_, records)| records.get(index).map_or(0_f64, AggregateSolarRecord::cost))
+                .collect::<Vec<_>>();
+
+            periods.push_record(Self::row(record.key(), &costs));
+        }
+
+        let totals = self.totals();
+        periods.push_record(Self::row("Total", &totals));
+
+        let mut ranking = Builder::default();
+        ranking.set_header(["Rank", self.label, "Total Cost", "Difference"]);
+
+        let cheapest = totals.first().copied().unwrap_or_default();
+
+        for (rank, (name, total)) in self.ranking().into_iter().zip(&totals).enumerate() {
+            ranking.push_record([
+                (rank + 1).to_string(),
+                name.to_owned(),
+                euro_to_string(total),
+                format!("+{}", euro_to_string(&(total - cheapest))),
+            ]);
+        }
+
+        let output = format!(
+            "{}\n{}\nCheapest: {} ({CHEAPEST})\n",
+            periods.build().with(Style::rounded()),
+            ranking.build().with(Style::rounded()),
+            self.tariffs[0].0,
+        );
+
+        write!(f, "{output}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::ensure;
+
+    use crate::{
+        investment::Investments, period::Period, solar_data::SolarData, solar_record::SolarRecord,
+        tariff::Tariff,
+    };
+
+    fn flat_tariff(name: &str, rate: f64) -> anyhow::Result<Tariff> {
+        Tariff::from_toml(&format!(
+            r#"
+            name = "{name}"
+
+            [[versions]]
+            name = "{name}"
+            start = "2024-01-01"
+            standing_charge = 0.5
+            export = 0.2
+
+            [[versions.bands]]
+            name = "Standard"
+            hours = [
+                0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11,
+                12, 13, 14, 15, 16, 17, 18, 19, 20, 21, 22, 23
+            ]
+            rate = {rate}
+        "#
+        ))
+    }
+
+    #[test]
+    fn test_compare() -> anyhow::Result<()> {
+        let records = SolarRecord::hourly("2024-06-01 00:00", 48, |_| (0, 500, -500, 0, 0))?;
+
+        let data = SolarData::new(
+            Investments::default(),
+            records,
+            Period::Day,
+            12,
+            flat_tariff("Current", 0.3)?,
+        );
+
+        let comparison = data.compare(&[flat_tariff("Cheap", 0.2)?, flat_tariff("Dear", 0.4)?]);
+
+        ensure!(comparison.ranking() == ["Cheap", "Current", "Dear"]);
+
+        // 48 hours at 500W, plus two days of standing charges
+        let totals = comparison.totals();
+        ensure!((totals[0] - (24_f64 * 0.2 + 1_f64)).abs() < 1e-9);
+
+        Ok(())
+    }
+}
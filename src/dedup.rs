@@ -0,0 +1,242 @@
+use core::fmt::{self, Display, Formatter};
+use std::path::PathBuf;
+
+use chrono::DateTime;
+use chrono_tz::Tz;
+use clap::ValueEnum;
+use serde::Serialize;
+use tabled::Tabled;
+
+use crate::solarman_record::SolarmanRecord;
+
+/// Which export wins when several contain a reading for the same time.
+#[non_exhaustive]
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Precedence {
+    /// The export whose path sorts first.
+    #[default]
+    First,
+    /// The export whose path sorts last.
+    Last,
+    /// The export with the most readings, e.g. a monthly over a daily one.
+    Largest,
+}
+
+impl Precedence {
+    /// Returns the rank of each file, lowest first to win.
+    fn ranks(self, files: &[(PathBuf, Vec<SolarmanRecord>)]) -> Vec<usize> {
+        let mut order = (0..files.len()).collect::<Vec<_>>();
+
+        match self {
+            Self::First => {}
+            Self::Last => order.reverse(),
+            Self::Largest => order.sort_by_key(|&index| {
+                core::cmp::Reverse(files.get(index).map_or(0, |(_, records)| records.len()))
+            }),
+        }
+
+        let mut ranks = vec![0; files.len()];
+        for (rank, index) in order.into_iter().enumerate() {
+            if let Some(slot) = ranks.get_mut(index) {
+                *slot = rank;
+            }
+        }
+
+        ranks
+    }
+}
+
+/// Two readings for the same time that disagree.
+#[derive(Debug, Clone, Tabled, Serialize)]
+pub struct Conflict {
+    #[tabled(rename = "Time")]
+    time: DateTime<Tz>,
+    #[tabled(rename = "Kept")]
+    kept: String,
+    #[tabled(rename = "Dropped")]
+    dropped: String,
+    #[tabled(rename = "Differences")]
+    differences: String,
+}
+
+/// What was merged away while loading overlapping exports.
+#[derive(Debug, Clone, Default)]
+pub struct Deduplication {
+    /// Number of readings dropped as duplicates.
+    dropped: usize,
+    conflicts: Vec<Conflict>,
+}
+
+impl Deduplication {
+    #[must_use]
+    #[inline]
+    pub fn dropped(&self) -> usize {
+        self.dropped
+    }
+
+    #[must_use]
+    #[inline]
+    pub fn conflicts(&self) -> &[Conflict] {
+        &self.conflicts
+    }
+}
+
+impl Display for Deduplication {
+    #[inline]
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Duplicates: {} readings dropped, {} with conflicting values",
+            self.dropped,
+            self.conflicts.len(),
+        )
+    }
+}
+
+/// Merges readings with the same resolved time, keeping the one from the
+/// export that takes `precedence`. Each reading is given with the index of
+/// its file in `files`. Returns the remaining readings sorted by time.
+#[must_use]
+pub(crate) fn deduplicate<'r>(
+    mut readings: Vec<(DateTime<Tz>, usize, &'r SolarmanRecord)>,
+    files: &[(PathBuf, Vec<SolarmanRecord>)],
+    precedence: Precedence,
+) -> (Vec<(DateTime<Tz>, &'r SolarmanRecord)>, Deduplication) {
+    let ranks = precedence.ranks(files);
+    let name = |file: usize| {
+        files.get(file).map_or_else(String::new, |(path, _)| {
+            path.file_name().map_or_else(
+                || path.display().to_string(),
+                |name| name.to_string_lossy().into_owned(),
+            )
+        })
+    };
+
+    readings.sort_by_key(|&(time, file, _)| (time, ranks.get(file).copied()));
+
+    let mut deduplication = Deduplication::default();
+    let mut kept: Vec<(DateTime<Tz>, usize, &SolarmanRecord)> = Vec::with_capacity(readings.len());
+
+    for (time, file, reading) in readings {
+        match kept.last() {
+            Some(&(kept_time, kept_file, kept_reading)) if kept_time == time => {
+                deduplication.dropped += 1;
+
+                let differences = differences(kept_reading, reading);
+                if !differences.is_empty() {
+                    deduplication.conflicts.push(Conflict {
+                        time,
+                        kept: name(kept_file),
+                        dropped: name(file),
+                        differences,
+                    });
+                }
+            }
+            _ => kept.push((time, file, reading)),
+        }
+    }
+
+    let kept = kept
+        .into_iter()
+        .map(|(time, _, reading)| (time, reading))
+        .collect();
+
+    (kept, deduplication)
+}
+
+/// Describes the values that differ between two readings.
+fn differences(kept: &SolarmanRecord, dropped: &SolarmanRecord) -> String {
+    [
+        ("production", kept.production, dropped.production, "W"),
+        ("consumption", kept.consumption, dropped.consumption, "W"),
+        ("grid", kept.grid, dropped.grid, "W"),
+        ("battery", kept.battery, dropped.battery, "W"),
+        ("SoC", i32::from(kept.soc), i32::from(dropped.soc), "%"),
+    ]
+    .into_iter()
+    .filter(|(_, kept, dropped, _)| kept != dropped)
+    .map(|(name, kept, dropped, unit)| format!("{name} {kept}{unit} vs {dropped}{unit}"))
+    .collect::<Vec<_>>()
+    .join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::{ensure, Context};
+    use chrono::{Duration, NaiveDateTime, TimeZone};
+    use chrono_tz::Europe::Dublin;
+
+    fn reading(production: i32) -> SolarmanRecord {
+        SolarmanRecord {
+            time: NaiveDateTime::default(),
+            production,
+            consumption: 0,
+            grid: production,
+            battery: 0,
+            soc: 0,
+            daily_production: None,
+        }
+    }
+
+    fn files() -> Vec<(PathBuf, Vec<SolarmanRecord>)> {
+        vec![
+            (PathBuf::from("day.csv"), vec![reading(1000), reading(2000)]),
+            (
+                PathBuf::from("month.csv"),
+                vec![reading(1000), reading(2500), reading(3000)],
+            ),
+        ]
+    }
+
+    fn merge(precedence: Precedence) -> anyhow::Result<(Vec<i32>, Deduplication)> {
+        let files = files();
+        let start = Dublin
+            .with_ymd_and_hms(2024, 6, 1, 12, 0, 0)
+            .single()
+            .context("Failed to create DateTime<Tz> value")?;
+
+        let readings = files
+            .iter()
+            .enumerate()
+            .flat_map(|(file, (_, records))| {
+                records.iter().zip(0..).map(move |(record, minute)| {
+                    (start + Duration::minutes(minute * 5), file, record)
+                })
+            })
+            .collect::<Vec<_>>();
+
+        let (kept, deduplication) = deduplicate(readings, &files, precedence);
+
+        Ok((
+            kept.iter().map(|(_, reading)| reading.production).collect(),
+            deduplication,
+        ))
+    }
+
+    #[test]
+    fn test_first() -> anyhow::Result<()> {
+        let (kept, deduplication) = merge(Precedence::First)?;
+
+        ensure!(kept == [1000, 2000, 3000]);
+        ensure!(deduplication.dropped() == 2);
+        ensure!(deduplication.conflicts().len() == 1);
+
+        let conflict = deduplication.conflicts().first().context("No conflict")?;
+        ensure!(conflict.kept == "day.csv");
+        ensure!(conflict.differences == "production 2000W vs 2500W, grid 2000W vs 2500W");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_largest() -> anyhow::Result<()> {
+        let (kept, _) = merge(Precedence::Largest)?;
+        ensure!(kept == [1000, 2500, 3000]);
+
+        let (kept, _) = merge(Precedence::Last)?;
+        ensure!(kept == [1000, 2500, 3000]);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,453 @@
+use std::collections::HashMap;
+
+use chrono::{DateTime, Duration, Months, NaiveDate, NaiveTime, TimeZone};
+use chrono_tz::Tz;
+use serde::{Deserialize, Deserializer};
+
+use crate::solar_record::SolarRecord;
+
+/// The day or month covered by a row of an energy report.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Span {
+    Day(NaiveDate),
+    /// A calendar month, by its first day.
+    Month(NaiveDate),
+}
+
+impl Span {
+    /// Returns the first date covered.
+    #[must_use]
+    pub fn first(self) -> NaiveDate {
+        match self {
+            Self::Day(date) | Self::Month(date) => date,
+        }
+    }
+
+    /// Returns the date after the last one covered.
+    #[must_use]
+    pub fn end(self) -> NaiveDate {
+        match self {
+            Self::Day(date) => date + Duration::days(1),
+            Self::Month(date) => date + Months::new(1),
+        }
+    }
+
+    /// Returns every date covered.
+    pub fn dates(self) -> impl Iterator<Item = NaiveDate> {
+        let end = self.end();
+        self.first().iter_days().take_while(move |date| *date < end)
+    }
+}
+
+/// A row of a Solarman daily or monthly report: the energy totals of a day
+/// or month rather than a power snapshot.
+#[derive(Debug, Clone, Copy, Deserialize, PartialEq)]
+pub(crate) struct EnergyRecord {
+    /// The day, or month for monthly reports, the totals cover.
+    #[serde(rename = "Time", deserialize_with = "deserialize_span")]
+    pub span: Span,
+    /// The energy produced, in kilowatt-hours.
+    #[serde(rename = "Production(kWh)", deserialize_with = "deserialize_energy")]
+    pub production: f64,
+    /// The energy consumed, in kilowatt-hours.
+    #[serde(rename = "Consumption(kWh)", deserialize_with = "deserialize_energy")]
+    pub consumption: f64,
+    /// The energy fed into the grid, in kilowatt-hours.
+    #[serde(rename = "Grid Feed-in(kWh)", deserialize_with = "deserialize_energy")]
+    pub feed_in: f64,
+    /// The energy bought from the grid, in kilowatt-hours.
+    #[serde(
+        rename = "Electricity Purchasing(kWh)",
+        deserialize_with = "deserialize_energy"
+    )]
+    pub purchased: f64,
+    /// The energy charged into the battery, in kilowatt-hours.
+    #[serde(
+        rename = "Charging(kWh)",
+        default,
+        deserialize_with = "deserialize_energy"
+    )]
+    pub charge: f64,
+    /// The energy discharged from the battery, in kilowatt-hours.
+    #[serde(
+        rename = "Discharging(kWh)",
+        default,
+        deserialize_with = "deserialize_energy"
+    )]
+    pub discharge: f64,
+}
+
+impl EnergyRecord {
+    /// Returns the totals of `records` as a daily row for `date`.
+    #[must_use]
+    pub fn from_records<'r, I>(date: NaiveDate, records: I) -> Self
+    where
+        I: IntoIterator<Item = &'r SolarRecord>,
+    {
+        records.into_iter().fold(
+            Self {
+                span: Span::Day(date),
+                production: 0_f64,
+                consumption: 0_f64,
+                feed_in: 0_f64,
+                purchased: 0_f64,
+                charge: 0_f64,
+                discharge: 0_f64,
+            },
+            |totals, record| Self {
+                production: totals.production + record.production() / 1000_f64,
+                consumption: totals.consumption + record.consumption() / 1000_f64,
+                feed_in: totals.feed_in + record.feed_in() / 1000_f64,
+                purchased: totals.purchased + record.purchased() / 1000_f64,
+                charge: totals.charge + record.battery_charge() / 1000_f64,
+                discharge: totals.discharge + record.battery_discharge() / 1000_f64,
+                ..totals
+            },
+        )
+    }
+
+    /// Returns the totals less those of `other`, stopping at zero.
+    #[must_use]
+    fn less(&self, other: &Self) -> Self {
+        let less = |total: f64, other: f64| (total - other).max(0_f64);
+
+        Self {
+            span: self.span,
+            production: less(self.production, other.production),
+            consumption: less(self.consumption, other.consumption),
+            feed_in: less(self.feed_in, other.feed_in),
+            purchased: less(self.purchased, other.purchased),
+            charge: less(self.charge, other.charge),
+            discharge: less(self.discharge, other.discharge),
+        }
+    }
+
+    /// Spreads the totals evenly over `dates`, local to `timezone`, as
+    /// half-hour records stamped, like readings, at the end of the time they
+    /// cover.
+    ///
+    /// A record holds net grid and battery power, so the halves alternate:
+    /// the first of each pair feeds in and charges, the second buys and
+    /// discharges, each at twice the mean rate.
+    #[must_use]
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    fn spread(&self, dates: &[NaiveDate], timezone: Tz) -> Vec<SolarRecord> {
+        let days = dates
+            .iter()
+            .map(|&date| {
+                (
+                    local_midnight(timezone, date),
+                    local_midnight(timezone, date + Duration::days(1)),
+                )
+            })
+            .collect::<Vec<_>>();
+
+        let seconds = days
+            .iter()
+            .map(|&(start, end)| (end - start).num_seconds())
+            .sum::<i64>();
+        let hours = (seconds as f64 / 3600_f64).max(1_f64);
+        let spread = |energy: f64, factor: f64| Spread::new(energy * 1000_f64 / hours * factor);
+
+        let mut production = spread(self.production, 1_f64);
+        let mut consumption = spread(self.consumption, 1_f64);
+        let mut feed_in = spread(self.feed_in, 2_f64);
+        let mut purchased = spread(self.purchased, 2_f64);
+        let mut charge = spread(self.charge, 2_f64);
+        let mut discharge = spread(self.discharge, 2_f64);
+
+        let mut records = Vec::new();
+
+        for (start, end) in days {
+            let mut time = start;
+
+            while time < end {
+                let next = (time + Duration::minutes(30)).min(end);
+                let duration = next - time;
+
+                let (grid, battery) = if records.len() % 2 == 0 {
+                    (feed_in.next(), charge.next())
+                } else {
+                    (-purchased.next(), -discharge.next())
+                };
+
+                records.push(SolarRecord::new(
+                    next,
+                    duration,
+                    production.next() as u32,
+                    consumption.next() as u32,
+                    grid as i32,
+                    battery as i32,
+                    0,
+                ));
+                time = next;
+            }
+        }
+
+        records
+    }
+}
+
+/// Returns the totals of `records` by the local date their time starts on.
+#[must_use]
+pub(crate) fn daily_totals<'r, I>(records: I) -> HashMap<NaiveDate, EnergyRecord>
+where
+    I: IntoIterator<Item = &'r SolarRecord>,
+{
+    let mut days = HashMap::<NaiveDate, Vec<&SolarRecord>>::new();
+
+    for record in records {
+        days.entry(record.start().date_naive())
+            .or_default()
+            .push(record);
+    }
+
+    days.into_iter()
+        .map(|(date, records)| (date, EnergyRecord::from_records(date, records)))
+        .collect()
+}
+
+/// Hands out a mean power as whole watts, carrying the rounding over to the
+/// next so that the total energy is kept.
+struct Spread {
+    power: f64,
+    carry: f64,
+}
+
+impl Spread {
+    fn new(power: f64) -> Self {
+        Self {
+            power: power.max(0_f64),
+            carry: 0_f64,
+        }
+    }
+
+    fn next(&mut self) -> f64 {
+        let power = (self.power + self.carry).round();
+        self.carry += self.power - power;
+        power
+    }
+}
+
+/// Turns report rows into records, local to `timezone`. Finer data wins
+/// where they overlap: daily rows are taken before monthly ones, and the dates
+/// in `covered`, with their totals, are left out. A row that is only partly
+/// covered spreads what its covered dates do not account for over the rest;
+/// one that is wholly covered is skipped.
+///
+/// Returns the records, sorted by time, and the number of rows skipped.
+#[must_use]
+pub(crate) fn report_records<'r, I>(
+    rows: I,
+    mut covered: HashMap<NaiveDate, EnergyRecord>,
+    timezone: Tz,
+) -> (Vec<SolarRecord>, usize)
+where
+    I: IntoIterator<Item = &'r EnergyRecord>,
+{
+    let mut rows = rows.into_iter().collect::<Vec<_>>();
+    rows.sort_by_key(|row| (matches!(row.span, Span::Month(_)), row.span.first()));
+
+    let mut records = Vec::new();
+    let mut skipped = 0;
+
+    for row in rows {
+        let uncovered = row
+            .span
+            .dates()
+            .filter(|date| !covered.contains_key(date))
+            .collect::<Vec<_>>();
+
+        if uncovered.is_empty() {
+            skipped += 1;
+            continue;
+        }
+
+        let rest = row
+            .span
+            .dates()
+            .filter_map(|date| covered.get(&date))
+            .fold(*row, |rest, day| rest.less(day));
+
+        let row_records = rest.spread(&uncovered, timezone);
+        covered.extend(daily_totals(&row_records));
+        records.extend(row_records);
+    }
+
+    records.sort_by_key(SolarRecord::date_time);
+
+    (records, skipped)
+}
+
+/// Returns the start of `date` in `timezone`.
+fn local_midnight(timezone: Tz, date: NaiveDate) -> DateTime<Tz> {
+    let midnight = date.and_time(NaiveTime::MIN);
+
+    timezone
+        .from_local_datetime(&midnight)
+        .earliest()
+        .unwrap_or_else(|| timezone.from_utc_datetime(&midnight))
+}
+
+/// Deserializes an energy total, in kilowatt-hours, from a string, treating an
+/// empty string as zero.
+///
+/// # Errors
+///
+/// Will return an error if the string cannot be parsed as a decimal value.
+fn deserialize_energy<'de, D>(deserializer: D) -> Result<f64, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let s = String::deserialize(deserializer)?;
+
+    if s.is_empty() {
+        return Ok(0_f64);
+    }
+
+    s.parse().map_err(serde::de::Error::custom)
+}
+
+/// Deserializes the day, or month, covered by a report row from a string.
+///
+/// # Errors
+///
+/// Will return an error if the string is neither a date nor a month.
+fn deserialize_span<'de, D>(deserializer: D) -> Result<Span, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let s = String::deserialize(deserializer)?;
+
+    let day = ["%Y/%m/%d", "%Y-%m-%d"]
+        .iter()
+        .find_map(|f| NaiveDate::parse_from_str(&s, f).ok())
+        .map(Span::Day);
+
+    let month = || {
+        [("%Y/%m/%d", "/01"), ("%Y-%m-%d", "-01")]
+            .iter()
+            .find_map(|(f, day)| NaiveDate::parse_from_str(&format!("{s}{day}"), f).ok())
+            .map(Span::Month)
+    };
+
+    day.or_else(month)
+        .ok_or(serde::de::Error::custom("Failed to parse date or month"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::{ensure, Context};
+    use chrono::Datelike;
+    use chrono_tz::Europe::Dublin;
+    use serde_test::{assert_de_tokens, Token};
+
+    fn row(span: Span) -> EnergyRecord {
+        EnergyRecord {
+            span,
+            production: 24_f64,
+            consumption: 12_f64,
+            feed_in: 18_f64,
+            purchased: 6_f64,
+            charge: 0_f64,
+            discharge: 0_f64,
+        }
+    }
+
+    /// Spreads the totals of `row` over all of its dates.
+    fn spread(row: &EnergyRecord) -> Vec<SolarRecord> {
+        row.spread(&row.span.dates().collect::<Vec<_>>(), Dublin)
+    }
+
+    #[test]
+    fn test_deserialize_energy_record() -> anyhow::Result<()> {
+        let input = [
+            Token::Struct {
+                name: "EnergyRecord",
+                len: 5,
+            },
+            Token::Str("Time"),
+            Token::Str("2024/06"),
+            Token::Str("Production(kWh)"),
+            Token::Str("24"),
+            Token::Str("Consumption(kWh)"),
+            Token::Str("12"),
+            Token::Str("Grid Feed-in(kWh)"),
+            Token::Str("18.0"),
+            Token::Str("Electricity Purchasing(kWh)"),
+            Token::Str("6"),
+            Token::StructEnd,
+        ];
+
+        let month = NaiveDate::from_ymd_opt(2024, 6, 1).context("Invalid date")?;
+
+        assert_de_tokens(&row(Span::Month(month)), &input);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_records() -> anyhow::Result<()> {
+        let date = NaiveDate::from_ymd_opt(2024, 6, 1).context("Invalid date")?;
+        let records = spread(&row(Span::Day(date)));
+
+        ensure!(records.len() == 48);
+        ensure!(records.iter().all(|r| r.start().date_naive() == date));
+
+        let total = |energy: fn(&SolarRecord) -> f64| records.iter().map(energy).sum::<f64>();
+        ensure!((total(SolarRecord::production) - 24_000_f64).abs() < 1e-9);
+        ensure!((total(SolarRecord::consumption) - 12_000_f64).abs() < 1e-9);
+        ensure!((total(SolarRecord::feed_in) - 18_000_f64).abs() < 1e-9);
+        ensure!((total(SolarRecord::purchased) - 6_000_f64).abs() < 1e-9);
+
+        // Clocks go back on the last Sunday of October, giving a 25 hour day.
+        let date = NaiveDate::from_ymd_opt(2024, 10, 27).context("Invalid date")?;
+        ensure!(spread(&row(Span::Day(date))).len() == 50);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_report_records() -> anyhow::Result<()> {
+        let june = NaiveDate::from_ymd_opt(2024, 6, 1).context("Invalid date")?;
+        let july = NaiveDate::from_ymd_opt(2024, 7, 1).context("Invalid date")?;
+        let month = |first| EnergyRecord {
+            production: 240_f64,
+            ..row(Span::Month(first))
+        };
+        let rows = [
+            month(june),
+            month(july),
+            row(Span::Day(july)),
+            row(Span::Day(july)),
+        ];
+
+        // Readings cover June 11 with 24 kWh of production.
+        let readings = spread(&row(Span::Day(june + Duration::days(10))));
+        let covered = daily_totals(&readings);
+
+        let (records, skipped) = report_records(&rows, covered, Dublin);
+
+        // Only the second July 1 row is wholly covered.
+        ensure!(skipped == 1);
+        ensure!(records.len() == (29 + 31) * 48);
+
+        let production = |first: NaiveDate| {
+            records
+                .iter()
+                .filter(|record| record.start().date_naive().month0() == first.month0())
+                .map(SolarRecord::production)
+                .sum::<f64>()
+        };
+
+        // The rest of each month's total is spread over the days left.
+        ensure!((production(june) - 216_000_f64).abs() < 1e-6);
+        ensure!((production(july) - 240_000_f64).abs() < 1e-6);
+        ensure!(!records
+            .iter()
+            .any(|record| record.start().date_naive() == june + Duration::days(10)));
+
+        Ok(())
+    }
+}
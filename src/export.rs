@@ -0,0 +1,80 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+use parsers::Worksheets;
+
+use crate::{
+    energy_record::EnergyRecord,
+    layout::{normalise, Format},
+    solarman_record::SolarmanRecord,
+};
+
+/// The Solarman exports in a folder, split by format.
+#[derive(Debug, Default)]
+pub(crate) struct Exports {
+    /// Power snapshots, taken every few minutes, by file.
+    pub power: Vec<(PathBuf, Vec<SolarmanRecord>)>,
+    /// Daily or monthly energy totals, by file.
+    pub energy: Vec<(PathBuf, Vec<EnergyRecord>)>,
+    /// Worksheets left out of a workbook with several as neither format, with
+    /// the reason.
+    pub skipped: Vec<(PathBuf, String)>,
+}
+
+impl Exports {
+    /// Parses every spreadsheet in the folder at `path`, telling power
+    /// exports from energy reports by their header row, whatever names and
+    /// units the inverter gives its columns.
+    ///
+    /// Each of the `worksheets` read from an Excel file counts as an export of
+    /// its own, named after the file and, if there are several, the sheet.
+    /// Of several, those in neither format are skipped rather than failing.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err`, naming the file, if the folder cannot be read or if
+    /// any spreadsheet in it fails to parse.
+    pub fn from_folder<P: AsRef<Path>>(path: P, worksheets: &Worksheets) -> anyhow::Result<Self> {
+        let mut exports = Self::default();
+
+        for path in parsers::spreadsheet_files(path)? {
+            let sheets = parsers::read_sheets(&path, worksheets)
+                .with_context(|| format!("Failed to read {}", path.display()))?;
+            let several = sheets.len() > 1;
+
+            for sheet in sheets {
+                let name = if several {
+                    PathBuf::from(format!("{}[{}]", path.display(), sheet.name))
+                } else {
+                    path.clone()
+                };
+
+                let context = || format!("Failed to read {}", name.display());
+
+                // A workbook may hold a summary or notes next to the export,
+                // which should not fail the load.
+                let (format, sheet) = match normalise(&sheet) {
+                    Ok(normalised) => normalised,
+                    Err(e) if several => {
+                        exports.skipped.push((name, e.to_string()));
+                        continue;
+                    }
+                    Err(e) => return Err(e.context(context())),
+                };
+
+                match format {
+                    Format::Power => {
+                        let records = sheet.deserialize().with_context(context)?;
+                        exports.power.push((name, records));
+                    }
+                    Format::Energy => {
+                        let records = sheet.deserialize().with_context(context)?;
+                        exports.energy.push((name, records));
+                    }
+                }
+            }
+        }
+
+        Ok(exports)
+    }
+}
@@ -0,0 +1,359 @@
+use core::fmt::{self, Display, Formatter};
+use std::path::Path;
+
+use chrono::{Duration, Months, NaiveDate};
+use parsers::csv;
+use serde::Serialize;
+use tabled::{settings::Style, Table, Tabled};
+
+use crate::{
+    formatting::{euro_to_string, percent_to_string},
+    investment::Investments,
+};
+
+/// What a financial analysis assumes about the lifetime of the system.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Assumptions {
+    /// Cost of money, as a fraction per year.
+    discount_rate: f64,
+    /// Electricity price rise, as a fraction per year.
+    escalation: f64,
+    /// Loss of panel output, as a fraction per year.
+    degradation: f64,
+    /// Maintenance cost per year.
+    maintenance: f64,
+    /// Year the inverter is replaced in, counting from 1.
+    inverter_year: Option<u32>,
+    inverter_cost: f64,
+    /// Number of years to project.
+    years: u32,
+}
+
+impl Assumptions {
+    /// Creates assumptions from rates given as percentages per year.
+    #[must_use]
+    #[inline]
+    pub fn new(
+        discount_rate: f64,
+        escalation: f64,
+        degradation: f64,
+        maintenance: f64,
+        inverter_year: Option<u32>,
+        inverter_cost: f64,
+        years: u32,
+    ) -> Self {
+        Self {
+            discount_rate: discount_rate / 100_f64,
+            escalation: escalation / 100_f64,
+            degradation: degradation / 100_f64,
+            maintenance,
+            inverter_year,
+            inverter_cost,
+            years,
+        }
+    }
+}
+
+/// The projected cash flow of one year of the system's lifetime.
+#[derive(Debug, Tabled, Serialize)]
+pub struct CashFlow {
+    #[tabled(rename = "Year")]
+    year: u32,
+    #[tabled(rename = "Ending")]
+    date: NaiveDate,
+    #[tabled(rename = "Savings", display_with = "euro_to_string")]
+    savings: f64,
+    #[tabled(rename = "Maintenance", display_with = "euro_to_string")]
+    maintenance: f64,
+    #[tabled(rename = "Inverter", display_with = "euro_to_string")]
+    inverter: f64,
+    /// Investments made during the year, net of grants.
+    #[tabled(rename = "Investment", display_with = "euro_to_string")]
+    investment: f64,
+    #[tabled(rename = "Net", display_with = "euro_to_string")]
+    net: f64,
+    #[tabled(rename = "Discounted", display_with = "euro_to_string")]
+    discounted: f64,
+    /// Discounted cash flow to date, less the setup cost.
+    #[tabled(rename = "Cumulative", display_with = "euro_to_string")]
+    cumulative: f64,
+}
+
+/// A discounted cash-flow analysis of the system over its lifetime.
+#[derive(Debug)]
+pub struct FinancialAnalysis {
+    setup_cost: f64,
+    start: NaiveDate,
+    cash_flows: Vec<CashFlow>,
+}
+
+impl FinancialAnalysis {
+    /// Projects `annual_savings`, as measured today, over the lifetime of a
+    /// system installed on `start`. Investments made by `start` make up the
+    /// setup cost; later ones are paid, and discounted, in the year they fall
+    /// in.
+    #[must_use]
+    pub(crate) fn new(
+        assumptions: Assumptions,
+        investments: &Investments,
+        annual_savings: f64,
+        start: NaiveDate,
+    ) -> Self {
+        let ending = |year: u32| {
+            start
+                .checked_add_months(Months::new(12 * year))
+                .unwrap_or(NaiveDate::MAX)
+        };
+        let invested = |from: Option<NaiveDate>, to: NaiveDate| {
+            investments
+                .iter()
+                .filter(|investment| {
+                    let date = investment.date().unwrap_or(start);
+                    from.is_none_or(|from| date > from) && date <= to
+                })
+                .fold(0_f64, |total, investment| total + investment.net())
+        };
+
+        let setup_cost = invested(None, start);
+        let mut cumulative = -setup_cost;
+
+        let cash_flows = (1..=assumptions.years)
+            .map(|year| {
+                let elapsed = f64::from(year - 1);
+                let date = ending(year);
+                let investment = invested(Some(ending(year - 1)), date);
+
+                let savings = annual_savings
+                    * (1_f64 + assumptions.escalation).powf(elapsed)
+                    * (1_f64 - assumptions.degradation).powf(elapsed);
+                let inverter = if assumptions.inverter_year == Some(year) {
+                    assumptions.inverter_cost
+                } else {
+                    0_f64
+                };
+
+                let net = savings - assumptions.maintenance - inverter - investment;
+                let discounted = net / (1_f64 + assumptions.discount_rate).powf(f64::from(year));
+                cumulative += discounted;
+
+                CashFlow {
+                    year,
+                    date,
+                    savings,
+                    maintenance: assumptions.maintenance,
+                    inverter,
+                    investment,
+                    net,
+                    discounted,
+                    cumulative,
+                }
+            })
+            .collect();
+
+        Self {
+            setup_cost,
+            start,
+            cash_flows,
+        }
+    }
+
+    /// Net present value of the system: its discounted cash flows less the
+    /// setup cost.
+    #[must_use]
+    #[inline]
+    pub fn npv(&self) -> f64 {
+        self.cash_flows
+            .last()
+            .map_or(-self.setup_cost, |flow| flow.cumulative)
+    }
+
+    /// Net present value of the cash flows at `rate`, less the setup cost.
+    fn npv_at(&self, rate: f64) -> f64 {
+        self.cash_flows
+            .iter()
+            .map(|flow| flow.net / (1_f64 + rate).powf(f64::from(flow.year)))
+            .sum::<f64>()
+            - self.setup_cost
+    }
+
+    /// Internal rate of return: the discount rate, as a fraction, at which the
+    /// net present value is zero. `None` if there is no such rate.
+    #[must_use]
+    #[inline]
+    pub fn irr(&self) -> Option<f64> {
+        let (mut low, mut high) = (-0.99_f64, 10_f64);
+
+        if self.npv_at(low).signum() == self.npv_at(high).signum() {
+            return None;
+        }
+
+        for _ in 0..200 {
+            let mid = low + (high - low) / 2_f64;
+
+            if self.npv_at(mid).signum() == self.npv_at(low).signum() {
+                low = mid;
+            } else {
+                high = mid;
+            }
+        }
+
+        Some(low + (high - low) / 2_f64)
+    }
+
+    /// Date from which the discounted cash flows cover the investments for
+    /// good, `start` if there was never anything to recover, or `None` if
+    /// they are still short at the end of the projection.
+    #[must_use]
+    #[inline]
+    #[allow(clippy::cast_possible_truncation)]
+    pub fn discounted_payback_date(&self) -> Option<NaiveDate> {
+        // A later investment can put the cash flows back in the red, so the
+        // payback comes after the last year that ends short.
+        let flow = match self
+            .cash_flows
+            .iter()
+            .rposition(|flow| flow.cumulative < 0_f64)
+        {
+            Some(index) => self.cash_flows.get(index + 1)?,
+            None if self.setup_cost <= 0_f64 => return Some(self.start),
+            None => self.cash_flows.first()?,
+        };
+
+        if flow.discounted <= 0_f64 {
+            return Some(flow.date);
+        }
+
+        let shortfall = flow.cumulative - flow.discounted;
+        let years = f64::from(flow.year - 1) - shortfall / flow.discounted;
+
+        self.start
+            .checked_add_signed(Duration::days((years * 365.25).round() as i64))
+    }
+
+    /// Writes the cash-flow table to a CSV file.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the file cannot be written.
+    #[inline]
+    pub fn write<P: AsRef<Path>>(&self, path: P) -> anyhow::Result<()> {
+        csv::write(path, &self.cash_flows)
+    }
+}
+
+impl Display for FinancialAnalysis {
+    #[inline]
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let mut table = Table::new(&self.cash_flows);
+        table.with(Style::rounded());
+
+        let irr = self.irr().map_or_else(
+            || "n/a".to_owned(),
+            |irr| percent_to_string(&(irr * 100_f64)),
+        );
+        let payback = self
+            .discounted_payback_date()
+            .map_or_else(|| "never".to_owned(), |date| date.to_string());
+
+        write!(
+            f,
+            "{table}\nNPV: {}\nIRR: {irr}\nDiscounted Payback Date: {payback}\n",
+            euro_to_string(&self.npv()),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::{ensure, Context};
+
+    use crate::investment::Investment;
+
+    fn start() -> anyhow::Result<NaiveDate> {
+        NaiveDate::from_ymd_opt(2024, 1, 1).context("Invalid date")
+    }
+
+    #[test]
+    fn test_undiscounted() -> anyhow::Result<()> {
+        let assumptions = Assumptions::new(0_f64, 0_f64, 0_f64, 0_f64, None, 0_f64, 20);
+        let analysis = FinancialAnalysis::new(
+            assumptions,
+            &Investments::lump_sum(1000_f64),
+            100_f64,
+            start()?,
+        );
+
+        ensure!((analysis.npv() - 1000_f64).abs() < 1e-9);
+        ensure!(analysis.discounted_payback_date() == NaiveDate::from_ymd_opt(2034, 1, 1));
+
+        // 1000 = 100 * (1 - (1 + r)^-20) / r
+        let irr = analysis.irr().context("No IRR")?;
+        ensure!((irr - 0.077_547).abs() < 1e-5, "IRR was {irr}");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_assumptions() -> anyhow::Result<()> {
+        let assumptions = Assumptions::new(5_f64, 10_f64, 10_f64, 10_f64, Some(2), 50_f64, 2);
+        let analysis = FinancialAnalysis::new(
+            assumptions,
+            &Investments::lump_sum(1000_f64),
+            100_f64,
+            start()?,
+        );
+
+        let second = analysis.cash_flows.get(1).context("Missing year 2")?;
+        ensure!((second.savings - 99_f64).abs() < 1e-9);
+        ensure!((second.net - 39_f64).abs() < 1e-9);
+        ensure!((second.discounted - 39_f64 / 1.1025).abs() < 1e-9);
+
+        ensure!(analysis.discounted_payback_date().is_none());
+        ensure!(analysis.npv() < 0_f64);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_dated_investment() -> anyhow::Result<()> {
+        let assumptions = Assumptions::new(0_f64, 0_f64, 0_f64, 0_f64, None, 0_f64, 20);
+        let date = NaiveDate::from_ymd_opt(2025, 6, 1).context("Invalid date")?;
+        let investments = Investments::lump_sum(1000_f64).with(Investment::new(
+            "Battery",
+            Some(date),
+            500_f64,
+            0_f64,
+        ));
+
+        let analysis = FinancialAnalysis::new(assumptions, &investments, 100_f64, start()?);
+
+        // The battery is paid in the second year, not up front.
+        let second = analysis.cash_flows.get(1).context("Missing year 2")?;
+        ensure!((second.investment - 500_f64).abs() < 1e-9);
+        ensure!((second.net + 400_f64).abs() < 1e-9);
+        ensure!((analysis.npv() - 500_f64).abs() < 1e-9);
+        ensure!(analysis.discounted_payback_date() == NaiveDate::from_ymd_opt(2039, 1, 1));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_nothing_to_recover() -> anyhow::Result<()> {
+        let assumptions = Assumptions::new(0_f64, 0_f64, 0_f64, 0_f64, None, 0_f64, 20);
+
+        let analysis =
+            FinancialAnalysis::new(assumptions, &Investments::lump_sum(0_f64), 0_f64, start()?);
+        ensure!(analysis.discounted_payback_date() == Some(start()?));
+
+        // Nothing is owed up front, but the battery bought in the second year
+        // takes until the end of the fifth to recover.
+        let date = NaiveDate::from_ymd_opt(2025, 6, 1).context("Invalid date")?;
+        let investments =
+            Investments::default().with(Investment::new("Battery", Some(date), 500_f64, 0_f64));
+        let analysis = FinancialAnalysis::new(assumptions, &investments, 100_f64, start()?);
+        ensure!(analysis.discounted_payback_date() == NaiveDate::from_ymd_opt(2028, 12, 31));
+
+        Ok(())
+    }
+}
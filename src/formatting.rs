@@ -17,6 +17,18 @@ pub(crate) fn watt_hour_to_string(value: &f64) -> String {
     }
 }
 
+#[allow(clippy::trivially_copy_pass_by_ref)]
+#[must_use]
+pub(crate) fn percent_to_string(value: &f64) -> String {
+    format!("{value:.1}%")
+}
+
+#[allow(clippy::trivially_copy_pass_by_ref)]
+#[must_use]
+pub(crate) fn decimal_to_string(value: &f64) -> String {
+    format!("{value:.2}")
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -34,6 +46,18 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_percent_to_string() -> anyhow::Result<()> {
+        let value = 87.654_f64;
+
+        ensure!(
+            percent_to_string(&value) == "87.7%",
+            anyhow!("Expected 87.7%, got {}", percent_to_string(&value))
+        );
+
+        Ok(())
+    }
+
     #[test]
     fn test_kwh_to_string() -> anyhow::Result<()> {
         let value = 123.45_f64;
@@ -0,0 +1,443 @@
+use core::fmt::{self, Display, Formatter};
+use std::collections::{HashMap, HashSet};
+
+use anyhow::ensure;
+use chrono::{DateTime, Duration};
+use chrono_tz::Tz;
+use clap::ValueEnum;
+use serde::Serialize;
+use tabled::Tabled;
+
+use crate::{
+    integration::Integration, profile::Profile, solar_record::SolarRecord,
+    solarman_record::SolarmanRecord,
+};
+
+/// How the time missing in a gap between readings is accounted for.
+#[non_exhaustive]
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum GapFill {
+    /// Leave the gap out, so its energy is not counted.
+    #[default]
+    Drop,
+    /// Fill the gap with readings interpolated between its two ends.
+    Interpolate,
+    /// Fill the gap with the mean readings for the same time of day.
+    Profile,
+}
+
+impl Display for GapFill {
+    #[inline]
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match *self {
+            Self::Drop => write!(f, "dropped"),
+            Self::Interpolate => write!(f, "interpolated"),
+            Self::Profile => write!(f, "filled from the profile"),
+        }
+    }
+}
+
+/// A stretch of time without readings.
+#[derive(Debug, Clone, Copy, Tabled, Serialize)]
+pub struct Gap {
+    /// Time of the last reading before the gap.
+    #[tabled(rename = "Gap Start")]
+    start: DateTime<Tz>,
+    /// Time of the first reading after the gap.
+    #[tabled(rename = "Gap End")]
+    end: DateTime<Tz>,
+    #[tabled(rename = "Length", display_with = "duration_to_string")]
+    #[serde(skip)]
+    length: Duration,
+}
+
+impl Gap {
+    #[must_use]
+    #[inline]
+    pub fn start(&self) -> DateTime<Tz> {
+        self.start
+    }
+
+    #[must_use]
+    #[inline]
+    pub fn end(&self) -> DateTime<Tz> {
+        self.end
+    }
+
+    #[must_use]
+    #[inline]
+    pub fn length(&self) -> Duration {
+        self.length
+    }
+}
+
+#[allow(clippy::trivially_copy_pass_by_ref)]
+fn duration_to_string(duration: &Duration) -> String {
+    format!(
+        "{}h{:02}m",
+        duration.num_hours(),
+        duration.num_minutes() % 60
+    )
+}
+
+/// How readings are turned into intervals and gaps between them handled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GapOptions {
+    /// The nominal time between readings; no reading covers more than this.
+    interval: Duration,
+    /// Time between readings above which they count as a gap.
+    threshold: Duration,
+    fill: GapFill,
+}
+
+impl Default for GapOptions {
+    #[inline]
+    fn default() -> Self {
+        Self {
+            interval: Duration::minutes(5),
+            threshold: Duration::minutes(15),
+            fill: GapFill::Drop,
+        }
+    }
+}
+
+impl GapOptions {
+    /// Creates options for readings `interval` apart, counting a longer time
+    /// than `threshold` between them as a gap to `fill`.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if `interval` is not positive or `threshold` is
+    /// shorter than it.
+    #[inline]
+    pub fn new(interval: Duration, threshold: Duration, fill: GapFill) -> anyhow::Result<Self> {
+        ensure!(
+            interval > Duration::zero(),
+            "The interval between readings must be positive"
+        );
+        ensure!(
+            threshold >= interval,
+            "The gap threshold must be at least the interval between readings"
+        );
+
+        Ok(Self {
+            interval,
+            threshold,
+            fill,
+        })
+    }
+
+    #[must_use]
+    #[inline]
+    pub fn interval(&self) -> Duration {
+        self.interval
+    }
+
+    #[must_use]
+    #[inline]
+    pub fn threshold(&self) -> Duration {
+        self.threshold
+    }
+
+    #[must_use]
+    #[inline]
+    pub fn fill(&self) -> GapFill {
+        self.fill
+    }
+
+    /// Turns readings, sorted by their resolved time, into records. Each
+    /// record covers the time since the previous reading, up to the nominal
+    /// interval; the first and any after a gap cover the interval. Power is
+    /// turned into energy over each interval by `integration`, and the rest of
+    /// each gap is then handled by `fill`.
+    ///
+    /// Readings at the `excluded` times still mark the intervals and gaps of
+    /// their neighbours, but are then left out, so the energy of the time
+    /// they cover is not counted.
+    ///
+    /// Returns the records, the gaps and the time between readings beyond the
+    /// interval but short of a gap, which is not counted either.
+    #[must_use]
+    pub(crate) fn records(
+        &self,
+        readings: &[(DateTime<Tz>, &SolarmanRecord)],
+        excluded: &HashSet<DateTime<Tz>>,
+        integration: Integration,
+    ) -> (Vec<SolarRecord>, Vec<Gap>, Duration) {
+        let mut gaps = Vec::new();
+        let mut missing = Duration::zero();
+        let mut previous: Option<DateTime<Tz>> = None;
+
+        let records = readings
+            .iter()
+            .map(|&(time, reading)| {
+                let elapsed = previous.map_or(self.interval, |previous| time - previous);
+
+                let duration = if elapsed > self.threshold {
+                    if let Some(start) = previous {
+                        gaps.push(Gap {
+                            start,
+                            end: time,
+                            length: elapsed,
+                        });
+                    }
+                    self.interval
+                } else {
+                    missing = missing + (elapsed - self.interval).max(Duration::zero());
+                    elapsed.min(self.interval)
+                };
+
+                previous = Some(time);
+                SolarRecord::from_solarman_record(reading, time, duration)
+            })
+            .filter(|record| !excluded.contains(&record.date_time()))
+            .collect::<Vec<_>>();
+
+        let records = integration.apply(&records, &gaps);
+
+        let records = match self.fill {
+            GapFill::Drop => records,
+            GapFill::Interpolate => {
+                self.fill_gaps(&records, &gaps, |before, after, time, duration| {
+                    before.interpolate(after, time, duration)
+                })
+            }
+            GapFill::Profile => {
+                let profile = PowerProfile::new(&records);
+                self.fill_gaps(&records, &gaps, |before, _, time, duration| {
+                    profile.record(time, duration, before.soc())
+                })
+            }
+        };
+
+        (records, gaps, missing)
+    }
+
+    /// Inserts records made by `fill` into each gap, up to the interval
+    /// covered by the reading that ends it.
+    fn fill_gaps<F>(&self, records: &[SolarRecord], gaps: &[Gap], fill: F) -> Vec<SolarRecord>
+    where
+        F: Fn(&SolarRecord, &SolarRecord, DateTime<Tz>, Duration) -> SolarRecord,
+    {
+        let mut filled = Vec::with_capacity(records.len());
+
+        for (index, record) in records.iter().enumerate() {
+            let before = index.checked_sub(1).and_then(|i| records.get(i));
+
+            if let Some(before) = before {
+                if gaps.iter().any(|gap| gap.end == record.date_time()) {
+                    let end = record.date_time() - self.interval;
+                    let mut time = before.date_time();
+
+                    while time < end {
+                        let next = (time + self.interval).min(end);
+                        filled.push(fill(before, record, next, next - time));
+                        time = next;
+                    }
+                }
+            }
+
+            filled.push(*record);
+        }
+
+        filled
+    }
+}
+
+/// The mean power flows for each 15-minute slot of the day.
+struct PowerProfile {
+    slots: HashMap<u32, (f64, f64, f64, f64)>,
+}
+
+impl PowerProfile {
+    fn new(records: &[SolarRecord]) -> Self {
+        let mut totals: HashMap<u32, (f64, f64, f64, f64)> = HashMap::new();
+
+        for record in records {
+            let (slot, _) = Profile::Slot.bucket(&record.date_time());
+            let entry = totals.entry(slot).or_default();
+
+            entry.0 += record.production();
+            entry.1 += record.consumption();
+            entry.2 += record.battery_charge() - record.battery_discharge();
+            entry.3 += record.duration().num_minutes() as f64 / 60_f64;
+        }
+
+        Self { slots: totals }
+    }
+
+    /// Returns a record with the mean power flows of the slot `time` falls in.
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    fn record(&self, time: DateTime<Tz>, duration: Duration, soc: u8) -> SolarRecord {
+        let (slot, _) = Profile::Slot.bucket(&time);
+        let (production, consumption, battery, hours) =
+            self.slots.get(&slot).copied().unwrap_or_default();
+        let hours = hours.max(f64::EPSILON);
+
+        let battery = (battery / hours).round() as i32;
+
+        SolarRecord::new(
+            time,
+            duration,
+            (production / hours).round() as u32,
+            (consumption / hours).round() as u32,
+            0,
+            battery,
+            soc,
+        )
+        .with_battery(battery, soc)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::{ensure, Context};
+    use chrono::{NaiveDateTime, TimeZone};
+    use chrono_tz::Europe::Dublin;
+
+    fn readings(minutes: &[i64]) -> anyhow::Result<Vec<(DateTime<Tz>, SolarmanRecord)>> {
+        let start = Dublin
+            .with_ymd_and_hms(2024, 6, 1, 12, 0, 0)
+            .single()
+            .context("Failed to create DateTime<Tz> value")?;
+
+        minutes
+            .iter()
+            .zip([1000, 2000, 3000, 4000])
+            .map(|(&minute, production)| {
+                let time = start + Duration::minutes(minute);
+
+                Ok((
+                    time,
+                    SolarmanRecord {
+                        time: NaiveDateTime::default(),
+                        production,
+                        consumption: 0,
+                        grid: production,
+                        battery: 0,
+                        soc: 0,
+                        daily_production: None,
+                    },
+                ))
+            })
+            .collect()
+    }
+
+    fn records(
+        fill: GapFill,
+        readings: &[(DateTime<Tz>, SolarmanRecord)],
+    ) -> anyhow::Result<(Vec<SolarRecord>, Vec<Gap>, Duration)> {
+        excluding(fill, readings, &HashSet::new())
+    }
+
+    fn excluding(
+        fill: GapFill,
+        readings: &[(DateTime<Tz>, SolarmanRecord)],
+        excluded: &HashSet<DateTime<Tz>>,
+    ) -> anyhow::Result<(Vec<SolarRecord>, Vec<Gap>, Duration)> {
+        let readings = readings
+            .iter()
+            .map(|(time, reading)| (*time, reading))
+            .collect::<Vec<_>>();
+        let options = GapOptions::new(Duration::minutes(5), Duration::minutes(15), fill)?;
+
+        Ok(options.records(&readings, excluded, Integration::Right))
+    }
+
+    #[test]
+    fn test_drop() -> anyhow::Result<()> {
+        let readings = readings(&[0, 5, 65, 70])?;
+        let (records, gaps, _) = records(GapFill::Drop, &readings)?;
+
+        ensure!(gaps.len() == 1);
+        ensure!(gaps[0].length() == Duration::hours(1));
+        ensure!(records.iter().all(|r| r.duration() == Duration::minutes(5)));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_interpolate() -> anyhow::Result<()> {
+        let readings = readings(&[0, 5, 65, 70])?;
+        let (records, _, _) = records(GapFill::Interpolate, &readings)?;
+
+        // 55 minutes of the gap are filled, the last 5 are covered by the
+        // reading that ends it.
+        ensure!(records.len() == 4 + 11);
+
+        let filled = records.get(2).context("Missing filled record")?;
+        ensure!(filled.date_time() == readings[1].0 + Duration::minutes(5));
+        ensure!((filled.production() - 2_083_f64 / 12_f64).abs() < 1e-9);
+
+        let total = records
+            .iter()
+            .map(|r| r.duration().num_minutes())
+            .sum::<i64>();
+        ensure!(total == 75);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_profile() -> anyhow::Result<()> {
+        let readings = readings(&[0, 5, 65, 70])?;
+        let (records, _, _) = records(GapFill::Profile, &readings)?;
+
+        ensure!(records.len() == 4 + 11);
+
+        // 12:10 falls in the 12:00 slot, whose mean production is 1500W.
+        let filled = records.get(2).context("Missing filled record")?;
+        ensure!((filled.production() - 1_500_f64 / 12_f64).abs() < 1e-9);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_excluded() -> anyhow::Result<()> {
+        let readings = readings(&[0, 5, 10, 15])?;
+        let excluded = HashSet::from([readings[2].0]);
+        let (records, gaps, _) = excluding(GapFill::Interpolate, &readings, &excluded)?;
+
+        // The reading after the excluded one covers only its own interval,
+        // so the excluded five minutes are not counted at all.
+        ensure!(gaps.is_empty());
+        ensure!(records.len() == 3);
+        ensure!(records.iter().all(|r| r.duration() == Duration::minutes(5)));
+        ensure!((records[2].production() - 4_000_f64 / 12_f64).abs() < 1e-9);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_short_gap() -> anyhow::Result<()> {
+        let readings = readings(&[0, 5, 17, 22])?;
+        let (records, gaps, missing) = records(GapFill::Drop, &readings)?;
+
+        // Twelve minutes is short of a gap, but the reading still covers only
+        // the five minute interval.
+        ensure!(gaps.is_empty());
+        ensure!(records.iter().all(|r| r.duration() == Duration::minutes(5)));
+        ensure!(missing == Duration::minutes(7));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_invalid_options() -> anyhow::Result<()> {
+        let options = |interval, threshold| {
+            GapOptions::new(
+                Duration::minutes(interval),
+                Duration::minutes(threshold),
+                GapFill::Drop,
+            )
+        };
+
+        ensure!(options(0, 15).is_err());
+        ensure!(options(-5, 15).is_err());
+        ensure!(options(5, 4).is_err());
+        ensure!(options(5, 5).is_ok());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,256 @@
+use core::fmt::{self, Display, Formatter};
+use std::{collections::BTreeMap, path::Path};
+
+use chrono::NaiveDate;
+use clap::ValueEnum;
+use parsers::csv;
+use serde::Serialize;
+use tabled::{settings::Style, Table, Tabled};
+
+use crate::{
+    formatting::{percent_to_string, watt_hour_to_string},
+    gap::Gap,
+    solar_record::SolarRecord,
+};
+
+/// How the power readings are turned into energy over the interval between
+/// each reading and the one before it.
+#[non_exhaustive]
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Integration {
+    /// Hold the reading that starts the interval.
+    Left,
+    /// Hold the reading that ends the interval.
+    #[default]
+    Right,
+    /// Average the readings at both ends of the interval.
+    Trapezoid,
+}
+
+impl Integration {
+    /// Returns the weight given to the reading that ends the interval.
+    fn weight(self) -> f64 {
+        match self {
+            Self::Left => 0_f64,
+            Self::Right => 1_f64,
+            Self::Trapezoid => 0.5_f64,
+        }
+    }
+
+    /// Returns the records with their power taken as the mean over each
+    /// interval. The first record and any after a gap have no reading before
+    /// them within the interval, so they keep their own.
+    #[must_use]
+    pub(crate) fn apply(self, records: &[SolarRecord], gaps: &[Gap]) -> Vec<SolarRecord> {
+        records
+            .iter()
+            .enumerate()
+            .map(|(index, record)| {
+                let previous = index.checked_sub(1).and_then(|i| records.get(i));
+
+                match previous {
+                    Some(previous) if !gaps.iter().any(|gap| gap.end() == record.date_time()) => {
+                        record.integrated(previous, self.weight())
+                    }
+                    _ => *record,
+                }
+            })
+            .collect()
+    }
+}
+
+impl Display for Integration {
+    #[inline]
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match *self {
+            Self::Left => write!(f, "left"),
+            Self::Right => write!(f, "right"),
+            Self::Trapezoid => write!(f, "trapezoid"),
+        }
+    }
+}
+
+/// The production of one day, integrated from the readings and as counted by
+/// the inverter.
+#[derive(Debug, Clone, Copy, Tabled, Serialize)]
+pub struct DailyYield {
+    #[tabled(rename = "Date")]
+    date: NaiveDate,
+    #[tabled(rename = "Integrated", display_with = "watt_hour_to_string")]
+    integrated: f64,
+    #[tabled(rename = "Inverter", display_with = "watt_hour_to_string")]
+    inverter: f64,
+    /// Integrated less inverter production, as a percentage of the latter.
+    #[tabled(rename = "Difference", display_with = "percent_to_string")]
+    difference: f64,
+}
+
+/// Daily production integrated from the readings against the inverter's own
+/// daily counter, for the days where the exports include it.
+#[derive(Debug)]
+pub struct YieldComparison {
+    integration: Integration,
+    days: Vec<DailyYield>,
+}
+
+impl YieldComparison {
+    /// Compares the production of `records`, integrated with `integration`,
+    /// against `counters`, the inverter's daily production in kWh.
+    #[must_use]
+    pub(crate) fn new(
+        integration: Integration,
+        records: &[SolarRecord],
+        counters: &BTreeMap<NaiveDate, f64>,
+    ) -> Self {
+        let mut integrated = BTreeMap::<NaiveDate, f64>::new();
+
+        for record in records {
+            *integrated
+                .entry(record.date_time().date_naive())
+                .or_default() += record.production();
+        }
+
+        let days = integrated
+            .into_iter()
+            .filter_map(|(date, integrated)| {
+                let inverter = counters.get(&date)? * 1000_f64;
+
+                Some(DailyYield {
+                    date,
+                    integrated,
+                    inverter,
+                    difference: difference(integrated, inverter),
+                })
+            })
+            .collect();
+
+        Self { integration, days }
+    }
+
+    #[must_use]
+    #[inline]
+    pub fn days(&self) -> &[DailyYield] {
+        &self.days
+    }
+
+    /// Writes the daily comparison to a CSV file.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the file cannot be written.
+    #[inline]
+    pub fn write<P: AsRef<Path>>(&self, path: P) -> anyhow::Result<()> {
+        csv::write(path, &self.days)
+    }
+}
+
+impl Display for YieldComparison {
+    #[inline]
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        if self.days.is_empty() {
+            return writeln!(f, "No daily production counter found in the exports");
+        }
+
+        let mut table = Table::new(&self.days);
+        table.with(Style::rounded());
+        writeln!(f, "{table}")?;
+
+        let integrated = self.days.iter().map(|day| day.integrated).sum::<f64>();
+        let inverter = self.days.iter().map(|day| day.inverter).sum::<f64>();
+
+        writeln!(f, "Integration: {}", self.integration)?;
+        writeln!(
+            f,
+            "Total: {} integrated, {} counted by the inverter ({})",
+            watt_hour_to_string(&integrated),
+            watt_hour_to_string(&inverter),
+            percent_to_string(&difference(integrated, inverter)),
+        )
+    }
+}
+
+/// Returns how far `integrated` is from `inverter`, as a percentage of the
+/// latter, or zero when the inverter counted nothing.
+fn difference(integrated: f64, inverter: f64) -> f64 {
+    if inverter > 0_f64 {
+        (integrated - inverter) / inverter * 100_f64
+    } else {
+        0_f64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::{ensure, Context};
+
+    /// Hourly records over a morning ramp from 0 W to 4000 W.
+    fn records() -> anyhow::Result<Vec<SolarRecord>> {
+        SolarRecord::hourly("2024-06-01 06:00", 5, |hour| {
+            let power = i32::try_from(hour * 1000).unwrap_or_default();
+            (power.unsigned_abs(), 0, power, 0, 0)
+        })
+    }
+
+    #[test]
+    fn test_apply() -> anyhow::Result<()> {
+        let records = records()?;
+        let production = |integration: Integration| {
+            integration
+                .apply(&records, &[])
+                .iter()
+                .map(SolarRecord::production)
+                .sum::<f64>()
+        };
+
+        // The first record has nothing before it, so keeps its own 0 W.
+        ensure!((production(Integration::Right) - 10_000_f64).abs() < 1e-9);
+        ensure!((production(Integration::Left) - 6_000_f64).abs() < 1e-9);
+        ensure!((production(Integration::Trapezoid) - 8_000_f64).abs() < 1e-9);
+
+        let feed_in = Integration::Trapezoid
+            .apply(&records, &[])
+            .iter()
+            .map(SolarRecord::feed_in)
+            .sum::<f64>();
+        ensure!((feed_in - 8_000_f64).abs() < 1e-9);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_reversal() -> anyhow::Result<()> {
+        let records = SolarRecord::hourly("2024-06-01 12:00", 2, |hour| {
+            let sign = if hour == 0 { -1 } else { 1 };
+            (0, 0, sign * 1000, -sign * 1000, 50)
+        })?;
+
+        // From 1000 W import to 1000 W export: half an hour's worth of each,
+        // not a net 0 W.
+        let integrated = Integration::Trapezoid.apply(&records, &[]);
+        let last = integrated.last().context("Missing record")?;
+        ensure!((last.purchased() - 500_f64).abs() < 1e-9);
+        ensure!((last.feed_in() - 500_f64).abs() < 1e-9);
+        ensure!((last.battery_charge() - 500_f64).abs() < 1e-9);
+        ensure!((last.battery_discharge() - 500_f64).abs() < 1e-9);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_yield_comparison() -> anyhow::Result<()> {
+        let records = Integration::Trapezoid.apply(&records()?, &[]);
+        let date = NaiveDate::from_ymd_opt(2024, 6, 1).context("Invalid date")?;
+        let other = NaiveDate::from_ymd_opt(2024, 6, 2).context("Invalid date")?;
+        let counters = BTreeMap::from([(date, 10_f64), (other, 12_f64)]);
+
+        let comparison = YieldComparison::new(Integration::Trapezoid, &records, &counters);
+
+        ensure!(comparison.days().len() == 1);
+        let day = comparison.days().first().context("Missing day")?;
+        ensure!((day.inverter - 10_000_f64).abs() < 1e-9);
+        ensure!((day.difference + 20_f64).abs() < 1e-9);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,282 @@
+use std::{ffi::OsStr, fs, path::Path};
+
+use anyhow::{anyhow, ensure, Context};
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use tabled::Tabled;
+
+use crate::formatting::euro_to_string;
+
+/// A capital cost, such as a set of panels or a battery, less any grant
+/// received towards it.
+#[derive(Debug, Clone, Deserialize, PartialEq)]
+pub struct Investment {
+    /// The name of the addition, e.g. "Panels".
+    name: String,
+    /// The day the cost was paid, or `None` if before the records start.
+    #[serde(default)]
+    date: Option<NaiveDate>,
+    /// The cost, in euro.
+    cost: f64,
+    /// Grants received towards the cost, in euro.
+    #[serde(default)]
+    grant: f64,
+}
+
+impl Investment {
+    #[must_use]
+    #[inline]
+    pub fn new(name: &str, date: Option<NaiveDate>, cost: f64, grant: f64) -> Self {
+        Self {
+            name: name.to_owned(),
+            date,
+            cost,
+            grant,
+        }
+    }
+
+    /// Returns the cost less grants.
+    #[must_use]
+    #[inline]
+    pub fn net(&self) -> f64 {
+        self.cost - self.grant
+    }
+
+    /// Returns the day the cost was paid, or `None` if before the records
+    /// start.
+    #[must_use]
+    #[inline]
+    pub fn date(&self) -> Option<NaiveDate> {
+        self.date
+    }
+}
+
+/// The capital costs of a system, loaded from a TOML or JSON investments file.
+#[derive(Debug, Clone, Default, Deserialize, PartialEq)]
+pub struct Investments {
+    investments: Vec<Investment>,
+}
+
+impl Investments {
+    /// A single cost paid before the records start.
+    #[must_use]
+    #[inline]
+    pub fn lump_sum(cost: f64) -> Self {
+        Self {
+            investments: vec![Investment::new("Setup", None, cost, 0_f64)],
+        }
+    }
+
+    /// Reads investments from a `.toml` or `.json` file.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the file cannot be read, has an unsupported
+    /// extension or does not list any investments.
+    #[inline]
+    pub fn from_file<P: AsRef<Path>>(path: P) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let contents = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read investments file {}", path.display()))?;
+
+        match path.extension().and_then(OsStr::to_str) {
+            Some("toml") => Self::from_toml(&contents),
+            Some("json") => Self::from_json(&contents),
+            _ => Err(anyhow!(
+                "Unsupported investments file extension: {}",
+                path.display()
+            )),
+        }
+        .with_context(|| format!("Invalid investments file {}", path.display()))
+    }
+
+    /// Parses investments from a TOML string.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the string does not list any investments.
+    #[inline]
+    pub fn from_toml(s: &str) -> anyhow::Result<Self> {
+        toml::from_str::<Self>(s)?.validate()
+    }
+
+    /// Parses investments from a JSON string.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the string does not list any investments.
+    #[inline]
+    pub fn from_json(s: &str) -> anyhow::Result<Self> {
+        serde_json::from_str::<Self>(s)?.validate()
+    }
+
+    /// Checks that no cost is negative or smaller than its grant, and sorts
+    /// the investments by date, undated ones first.
+    fn validate(mut self) -> anyhow::Result<Self> {
+        ensure!(!self.investments.is_empty(), "No investments listed");
+
+        for investment in &self.investments {
+            let Investment {
+                ref name,
+                cost,
+                grant,
+                ..
+            } = *investment;
+
+            ensure!(cost >= 0_f64, "{name} has a negative cost of {cost}");
+            ensure!(grant >= 0_f64, "{name} has a negative grant of {grant}");
+            ensure!(
+                grant <= cost,
+                "{name} has a grant of {grant}, more than its cost of {cost}"
+            );
+        }
+
+        self.investments.sort_by_key(|investment| investment.date);
+
+        Ok(self)
+    }
+
+    /// Returns a copy with `investment` added.
+    #[must_use]
+    pub(crate) fn with(&self, investment: Investment) -> Self {
+        let mut investments = self.investments.clone();
+        investments.push(investment);
+        investments.sort_by_key(|investment| investment.date);
+
+        Self { investments }
+    }
+
+    /// Returns the total cost less grants.
+    #[must_use]
+    #[inline]
+    pub fn total(&self) -> f64 {
+        self.investments.iter().map(Investment::net).sum()
+    }
+
+    #[must_use]
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.investments.len()
+    }
+
+    #[must_use]
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.investments.is_empty()
+    }
+
+    /// Returns the investments, oldest first.
+    #[inline]
+    pub fn iter(&self) -> core::slice::Iter<'_, Investment> {
+        self.investments.iter()
+    }
+}
+
+impl<'i> IntoIterator for &'i Investments {
+    type Item = &'i Investment;
+    type IntoIter = core::slice::Iter<'i, Investment>;
+
+    #[inline]
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// When one investment was, or is expected to be, paid back, counting
+/// savings against investments in the order they were made. Savings beyond
+/// what is owed carry forward, so an investment made after the earlier ones
+/// were paid back starts from the surplus built up by then.
+#[derive(Debug, Tabled, Serialize)]
+pub struct Payback {
+    #[tabled(rename = "Investment")]
+    name: String,
+    #[tabled(rename = "Date", display_with("Self::date_cell", self))]
+    date: Option<NaiveDate>,
+    #[tabled(rename = "Cost", display_with = "euro_to_string")]
+    cost: f64,
+    #[tabled(rename = "Grant", display_with = "euro_to_string")]
+    grant: f64,
+    /// Net investment to date, including this one.
+    #[tabled(rename = "Cumulative", display_with = "euro_to_string")]
+    cumulative: f64,
+    #[tabled(rename = "Paid Back", display_with("Self::paid_back_cell", self))]
+    paid_back: Option<NaiveDate>,
+}
+
+impl Payback {
+    #[must_use]
+    pub(crate) fn new(
+        investment: &Investment,
+        cumulative: f64,
+        paid_back: Option<NaiveDate>,
+    ) -> Self {
+        Self {
+            name: investment.name.clone(),
+            date: investment.date,
+            cost: investment.cost,
+            grant: investment.grant,
+            cumulative,
+            paid_back,
+        }
+    }
+
+    #[must_use]
+    #[inline]
+    pub fn paid_back(&self) -> Option<NaiveDate> {
+        self.paid_back
+    }
+
+    fn date_cell(&self) -> String {
+        date_to_string(self.date)
+    }
+
+    fn paid_back_cell(&self) -> String {
+        date_to_string(self.paid_back)
+    }
+}
+
+fn date_to_string(date: Option<NaiveDate>) -> String {
+    date.map_or_else(|| "-".to_owned(), |date| date.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_investments() -> anyhow::Result<()> {
+        let investments = Investments::from_toml(
+            r#"
+            [[investments]]
+            name = "Battery"
+            date = "2024-03-01"
+            cost = 4000
+
+            [[investments]]
+            name = "Panels"
+            date = "2023-05-01"
+            cost = 11000
+            grant = 2400
+        "#,
+        )?;
+
+        ensure!(investments.len() == 2);
+        ensure!(investments.iter().next().map(|i| i.name.as_str()) == Some("Panels"));
+        ensure!((investments.total() - 12_600_f64).abs() < f64::EPSILON);
+
+        ensure!(Investments::from_toml("investments = []").is_err());
+
+        let invalid = |cost: f64, grant: f64| {
+            Investments::from_toml(&format!(
+                "[[investments]]\nname = \"Panels\"\ncost = {cost}\ngrant = {grant}"
+            ))
+            .is_err()
+        };
+        ensure!(invalid(-100_f64, 0_f64));
+        ensure!(invalid(100_f64, 200_f64));
+        ensure!(invalid(100_f64, -10_f64));
+        ensure!(!invalid(100_f64, 100_f64));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,531 @@
+use parsers::Sheet;
+
+/// What a column measures, which decides the units it may be given in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Quantity {
+    /// Power, read in watts.
+    Power,
+    /// Energy, read in kilowatt-hours.
+    Energy,
+    /// Anything else, read as it is.
+    Other,
+}
+
+impl Quantity {
+    /// Returns the factor turning values in `unit` into this quantity's own
+    /// unit, or `None` if the unit does not measure it. Columns without a
+    /// unit are taken to be in the quantity's own unit.
+    fn scale(self, unit: Option<&str>) -> Option<f64> {
+        let Some(unit) = unit else {
+            return Some(1_f64);
+        };
+
+        match (self, unit.to_lowercase().as_str()) {
+            (Self::Power, "w") | (Self::Energy, "kwh") | (Self::Other, _) => Some(1_f64),
+            (Self::Power, "kw") | (Self::Energy, "mwh") => Some(1000_f64),
+            (Self::Energy, "wh") => Some(0.001_f64),
+            _ => None,
+        }
+    }
+}
+
+/// A column a record reads, with the names it is known by in different
+/// exports.
+#[derive(Debug)]
+struct Column {
+    /// The header the record deserializes the column from.
+    header: &'static str,
+    /// Known names, lower-cased with only letters and digits kept, and
+    /// without the unit. Only the English names are known, so exports made
+    /// with Solarman set to another language are not recognised.
+    names: &'static [&'static str],
+    quantity: Quantity,
+    required: bool,
+    /// Known names of a column read as positive and one read as negative
+    /// which, when both are there, stand in for a signed column missing from
+    /// the export.
+    net: Option<(&'static [&'static str], &'static [&'static str])>,
+}
+
+impl Column {
+    const fn new(
+        header: &'static str,
+        names: &'static [&'static str],
+        quantity: Quantity,
+        required: bool,
+    ) -> Self {
+        Self {
+            header,
+            names,
+            quantity,
+            required,
+            net: None,
+        }
+    }
+
+    /// Returns the column, read as the column named by `positive` less the
+    /// one named by `negative` when it is not found itself.
+    const fn net(
+        self,
+        positive: &'static [&'static str],
+        negative: &'static [&'static str],
+    ) -> Self {
+        Self {
+            net: Some((positive, negative)),
+            ..self
+        }
+    }
+
+    /// Returns the factor to scale the values of a column with `header` by,
+    /// if it has one of `names`.
+    fn matches(&self, names: &[&str], header: &str) -> Option<f64> {
+        let (name, unit) = split(header);
+
+        if names.contains(&name.as_str()) {
+            self.quantity.scale(unit.as_deref())
+        } else {
+            None
+        }
+    }
+}
+
+/// The format of a Solarman export.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Format {
+    /// Power snapshots, taken every few minutes.
+    Power,
+    /// Daily or monthly energy totals.
+    Energy,
+}
+
+impl Format {
+    fn columns(self) -> &'static [Column] {
+        match self {
+            Self::Power => &POWER_COLUMNS,
+            Self::Energy => &ENERGY_COLUMNS,
+        }
+    }
+}
+
+const POWER_COLUMNS: [Column; 7] = [
+    Column::new(
+        "Updated Time",
+        &["updatedtime", "updatetime", "time", "datetime", "timestamp"],
+        Quantity::Other,
+        true,
+    ),
+    Column::new(
+        "Production Power(W)",
+        &[
+            "productionpower",
+            "totalproductionpower",
+            "pvpower",
+            "totalpvpower",
+            "solarpower",
+        ],
+        Quantity::Power,
+        true,
+    ),
+    Column::new(
+        "Consumption Power(W)",
+        &[
+            "consumptionpower",
+            "totalconsumptionpower",
+            "loadpower",
+            "totalloadpower",
+        ],
+        Quantity::Power,
+        true,
+    ),
+    // Some inverters only give the feed-in, which is never negative, next to
+    // the power bought.
+    Column::new(
+        "Grid Power(W)",
+        &["gridpower", "totalgridpower"],
+        Quantity::Power,
+        true,
+    )
+    .net(
+        &["gridfeedinpower", "feedinpower", "exportpower"],
+        &[
+            "purchasingpower",
+            "electricitypurchasingpower",
+            "gridpurchasepower",
+            "gridimportpower",
+        ],
+    ),
+    Column::new(
+        "Battery Power(W)",
+        &["batterypower", "totalbatterypower"],
+        Quantity::Power,
+        false,
+    ),
+    Column::new(
+        "SoC(%)",
+        &["soc", "batterysoc", "stateofcharge"],
+        Quantity::Other,
+        false,
+    ),
+    Column::new(
+        "Daily Production(kWh)",
+        &[
+            "dailyproduction",
+            "dailyproductionactive",
+            "dailyyield",
+            "todayproduction",
+        ],
+        Quantity::Energy,
+        false,
+    ),
+];
+
+const ENERGY_COLUMNS: [Column; 7] = [
+    Column::new("Time", &["time", "date", "month"], Quantity::Other, true),
+    Column::new(
+        "Production(kWh)",
+        &["production", "totalproduction", "pvproduction", "yield"],
+        Quantity::Energy,
+        true,
+    ),
+    Column::new(
+        "Consumption(kWh)",
+        &["consumption", "totalconsumption", "loadconsumption"],
+        Quantity::Energy,
+        true,
+    ),
+    Column::new(
+        "Grid Feed-in(kWh)",
+        &["gridfeedin", "feedin", "gridexport"],
+        Quantity::Energy,
+        true,
+    ),
+    Column::new(
+        "Electricity Purchasing(kWh)",
+        &[
+            "electricitypurchasing",
+            "purchasing",
+            "gridpurchase",
+            "gridimport",
+        ],
+        Quantity::Energy,
+        true,
+    ),
+    Column::new(
+        "Charging(kWh)",
+        &["charging", "batterycharging", "chargingcapacity"],
+        Quantity::Energy,
+        false,
+    ),
+    Column::new(
+        "Discharging(kWh)",
+        &["discharging", "batterydischarging", "dischargingcapacity"],
+        Quantity::Energy,
+        false,
+    ),
+];
+
+/// A header matched to a column: its position, the header the record reads
+/// and the factor its values are scaled by.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Match {
+    index: usize,
+    header: &'static str,
+    scale: f64,
+    /// The position and factor of a column subtracted from this one, when
+    /// the column is read as the difference of two.
+    less: Option<(usize, f64)>,
+}
+
+/// Matches `headers` against the columns of `format`, returning the matches
+/// or the headers of the required columns not found.
+fn resolve(format: Format, headers: &[String]) -> Result<Vec<Match>, Vec<&'static str>> {
+    let mut matches = Vec::new();
+    let mut missing = Vec::new();
+
+    for column in format.columns() {
+        let find = |names: &[&str]| {
+            headers.iter().enumerate().find_map(|(index, header)| {
+                let taken = matches.iter().any(|m: &Match| {
+                    m.index == index || m.less.is_some_and(|(less, _)| less == index)
+                });
+                let scale = column.matches(names, header).filter(|_| !taken)?;
+
+                Some((index, scale))
+            })
+        };
+
+        let found = find(column.names)
+            .map(|(index, scale)| (index, scale, None))
+            .or_else(|| {
+                let (positive, negative) = column.net?;
+                let (index, scale) = find(positive)?;
+
+                Some((index, scale, Some(find(negative)?)))
+            })
+            .map(|(index, scale, less)| Match {
+                index,
+                header: column.header,
+                scale,
+                less,
+            });
+
+        match found {
+            Some(found) => matches.push(found),
+            None if column.required => missing.push(column.header),
+            None => {}
+        }
+    }
+
+    if missing.is_empty() {
+        Ok(matches)
+    } else {
+        Err(missing)
+    }
+}
+
+/// Works out the format of `sheet` from its headers and rewrites it in that
+/// format's own headers and units, leaving out columns it does not read.
+/// Headers are matched in English only, whatever the model or firmware.
+///
+/// # Errors
+///
+/// Will return `Err`, listing the columns missing and those found, if the
+/// headers match neither format.
+pub(crate) fn normalise(sheet: &Sheet) -> anyhow::Result<(Format, Sheet)> {
+    let (format, matches) = match resolve(Format::Power, &sheet.headers) {
+        Ok(matches) => (Format::Power, matches),
+        Err(power) => match resolve(Format::Energy, &sheet.headers) {
+            Ok(matches) => (Format::Energy, matches),
+            Err(energy) => {
+                let (kind, missing) = if energy.len() < power.len() {
+                    ("energy report", energy)
+                } else {
+                    ("power export", power)
+                };
+
+                anyhow::bail!(
+                    "Not a recognised Solarman export: missing {kind} columns {}; found {}",
+                    missing.join(", "),
+                    sheet.headers.join(", "),
+                );
+            }
+        },
+    };
+
+    let rows = sheet
+        .rows
+        .iter()
+        .map(|row| {
+            matches
+                .iter()
+                .map(|m| {
+                    let cell = |index: usize| row.get(index).map_or("", String::as_str);
+
+                    match m.less {
+                        Some((less, factor)) => {
+                            difference(cell(m.index), m.scale, cell(less), factor)
+                        }
+                        None => scale(cell(m.index), m.scale),
+                    }
+                })
+                .collect()
+        })
+        .collect();
+
+    let sheet = Sheet {
+        name: sheet.name.clone(),
+        headers: matches.iter().map(|m| m.header.to_owned()).collect(),
+        rows,
+    };
+
+    Ok((format, sheet))
+}
+
+/// Splits a header into its name, lower-cased with only letters and digits
+/// kept, and the unit in a trailing "(unit)" or "[unit]", if any.
+fn split(header: &str) -> (String, Option<String>) {
+    let header = header.trim();
+
+    let unit = header
+        .strip_suffix(')')
+        .and_then(|rest| rest.rsplit_once('('))
+        .or_else(|| {
+            header
+                .strip_suffix(']')
+                .and_then(|rest| rest.rsplit_once('['))
+        });
+
+    let (name, unit) = match unit {
+        Some((name, unit)) if !unit.is_empty() => (name, Some(unit.trim().to_owned())),
+        _ => (header, None),
+    };
+
+    let name = name
+        .chars()
+        .filter(char::is_ascii_alphanumeric)
+        .map(|c| c.to_ascii_lowercase())
+        .collect();
+
+    (name, unit)
+}
+
+/// Scales a numeric cell by `factor`, leaving other cells as they are. The
+/// result is rounded to a millionth so that, say, 0.29 kW reads as 290 W
+/// rather than just under it.
+fn scale(cell: &str, factor: f64) -> String {
+    if (factor - 1_f64).abs() < f64::EPSILON {
+        return cell.to_owned();
+    }
+
+    cell.trim()
+        .parse::<f64>()
+        .map_or_else(|_| cell.to_owned(), |value| round(value * factor))
+}
+
+/// Writes `value` rounded to a millionth.
+fn round(value: f64) -> String {
+    ((value * 1e6_f64).round() / 1e6_f64).to_string()
+}
+
+/// Subtracts `other`, scaled by `other_factor`, from `cell`, scaled by
+/// `factor`, taking an empty `other` as zero. Leaves `cell` as it is if either
+/// is not numeric.
+fn difference(cell: &str, factor: f64, other: &str, other_factor: f64) -> String {
+    let other = if other.trim().is_empty() { "0" } else { other };
+
+    match (cell.trim().parse::<f64>(), other.trim().parse::<f64>()) {
+        (Ok(value), Ok(other)) => round(value * factor - other * other_factor),
+        _ => cell.to_owned(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::ensure;
+
+    fn sheet(headers: &[&str], row: &[&str]) -> Sheet {
+        Sheet {
+            name: String::new(),
+            headers: headers.iter().map(|&h| h.to_owned()).collect(),
+            rows: vec![row.iter().map(|&c| c.to_owned()).collect()],
+        }
+    }
+
+    #[test]
+    fn test_split() -> anyhow::Result<()> {
+        ensure!(
+            split("Production Power(W)") == ("productionpower".to_owned(), Some("W".to_owned()))
+        );
+        ensure!(
+            split("Daily Production (Active)(kWh)")
+                == ("dailyproductionactive".to_owned(), Some("kWh".to_owned()))
+        );
+        ensure!(split("Total Production Power") == ("totalproductionpower".to_owned(), None));
+        ensure!(split("PV Power [kW]") == ("pvpower".to_owned(), Some("kW".to_owned())));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_normalise_power() -> anyhow::Result<()> {
+        let input = sheet(
+            &[
+                "Time",
+                "Total Production Power(kW)",
+                "Load Power(kW)",
+                "Total Grid Power(W)",
+                "Inverter Temperature(℃)",
+            ],
+            &["2024/06/01 12:00", "1.25", "0.29", "850", "41"],
+        );
+
+        let (format, output) = normalise(&input)?;
+
+        ensure!(format == Format::Power);
+        ensure!(
+            output.headers
+                == [
+                    "Updated Time",
+                    "Production Power(W)",
+                    "Consumption Power(W)",
+                    "Grid Power(W)"
+                ]
+        );
+        ensure!(output.rows == [["2024/06/01 12:00", "1250", "290", "850"]]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_normalise_feed_in() -> anyhow::Result<()> {
+        let headers = [
+            "Time",
+            "Production Power(W)",
+            "Consumption Power(W)",
+            "Grid Feed-in Power(W)",
+            "Purchasing Power(kW)",
+        ];
+
+        // Feed-in alone is never negative, so cannot stand for the grid.
+        ensure!(normalise(&sheet(&headers[..4], &["2024/06/01 12:00", "0", "0", "0"])).is_err());
+
+        let grid = |feed_in: &str, purchase: &str| -> anyhow::Result<Vec<Vec<String>>> {
+            let input = sheet(
+                &headers,
+                &["2024/06/01 12:00", "100", "1300", feed_in, purchase],
+            );
+            let (_, output) = normalise(&input)?;
+
+            ensure!(output.headers.get(3).map(String::as_str) == Some("Grid Power(W)"));
+            Ok(output.rows)
+        };
+
+        ensure!(grid("0", "1.2")? == [["2024/06/01 12:00", "100", "1300", "-1200"]]);
+        ensure!(grid("850", "")? == [["2024/06/01 12:00", "100", "1300", "850"]]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_normalise_energy() -> anyhow::Result<()> {
+        let input = sheet(
+            &[
+                "Date",
+                "Production (kWh)",
+                "Consumption (kWh)",
+                "Grid Feed-in (Wh)",
+                "Electricity Purchasing (kWh)",
+            ],
+            &["2024/06/01", "20", "12", "14300", "6"],
+        );
+
+        let (format, output) = normalise(&input)?;
+
+        ensure!(format == Format::Energy);
+        ensure!(output.rows == [["2024/06/01", "20", "12", "14.3", "6"]]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_normalise_missing() -> anyhow::Result<()> {
+        let input = sheet(
+            &["Updated Time", "Production Power(W)", "Grid Power(%)"],
+            &["2024/06/01 12:00", "1250", "3"],
+        );
+
+        let Err(error) = normalise(&input) else {
+            anyhow::bail!("Expected a missing column error");
+        };
+
+        ensure!(
+            error.to_string()
+                == "Not a recognised Solarman export: missing power export columns \
+                    Consumption Power(W), Grid Power(W); \
+                    found Updated Time, Production Power(W), Grid Power(%)"
+        );
+
+        Ok(())
+    }
+}
@@ -16,9 +16,24 @@
 )]
 
 pub mod aggregate_solar_record;
+pub mod battery;
+pub mod check;
+pub mod comparison;
+pub mod dedup;
+pub mod energy_record;
+pub mod export;
+pub mod finance;
 pub mod formatting;
+pub mod gap;
+pub mod integration;
+pub mod investment;
+pub mod layout;
 pub mod period;
+pub mod profile;
 pub mod rate;
+pub mod scaling;
+pub mod seasonal;
 pub mod solar_data;
 pub mod solar_record;
 pub mod solarman_record;
+pub mod tariff;
@@ -1,9 +1,216 @@
-use clap::Parser;
-use solar_rs::{period::Period, solar_data::SolarData};
+use std::path::PathBuf;
+
+use chrono::{Duration, NaiveDate};
+use chrono_tz::Tz;
+use clap::{ArgGroup, Args, Parser, Subcommand, ValueEnum};
+use parsers::Worksheets;
+use solar_rs::{
+    battery::{Battery, Dispatch},
+    check::{CheckReport, Rules},
+    dedup::Precedence,
+    finance::Assumptions,
+    gap::{GapFill, GapOptions},
+    integration::Integration,
+    investment::Investments,
+    period::Period,
+    profile::Profile,
+    solar_data::{LoadOptions, SolarData},
+    tariff::Tariff,
+};
 
 #[derive(Parser, Debug)]
-struct Args {
-    path: String,
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    #[command(flatten)]
+    report: ReportArgs,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Replay the history against candidate tariffs and rank them by cost
+    Compare(CompareArgs),
+    /// Show the average day, bucketed by time of day, weekday or month
+    Profile(ProfileArgs),
+    /// Replay the history with a virtual battery and estimate its payback
+    Simulate(SimulateArgs),
+    /// Replay the history with a larger or smaller PV array
+    Scale(ScaleArgs),
+    /// Project the savings over the system's lifetime: NPV, IRR and payback
+    Finance(FinanceArgs),
+    /// Project the cumulative savings month by month until payoff
+    Projection(ProjectionArgs),
+    /// Report readings that break the energy balance or look implausible
+    Check(CheckArgs),
+    /// Compare the integrated daily production with the inverter's counter
+    Yield(YieldArgs),
+}
+
+// Where to load the Solarman exports from and how to price them.
+#[derive(Args, Debug)]
+struct DataArgs {
+    #[arg(required = true)]
+    path: Option<String>,
+
+    /// TOML or JSON tariff file; defaults to the built-in tariff history
+    #[arg(short, long, value_name = "FILE")]
+    tariff: Option<PathBuf>,
+
+    /// Timezone of the Solarman timestamps, also used to assign tariff bands
+    #[arg(long, default_value = "Europe/Dublin")]
+    timezone: Tz,
+
+    /// Only use records on or after this date (YYYY-MM-DD)
+    #[arg(long, value_name = "DATE")]
+    from: Option<NaiveDate>,
+
+    /// Only use records on or before this date (YYYY-MM-DD)
+    #[arg(long, value_name = "DATE")]
+    to: Option<NaiveDate>,
+
+    /// Leave out readings that break the balance and plausibility rules
+    #[arg(long)]
+    exclude_invalid: bool,
+
+    #[command(flatten)]
+    rules: RuleArgs,
+
+    #[command(flatten)]
+    gaps: GapArgs,
+
+    /// Which export wins when several have a reading for the same time
+    #[arg(long, value_enum, default_value_t = Precedence::First)]
+    precedence: Precedence,
+
+    /// How power readings are turned into energy between readings
+    #[arg(long, value_enum, default_value_t = Integration::Right)]
+    integration: Integration,
+
+    /// Worksheet to read from Excel exports: a name, a position counting from
+    /// 1, or all
+    #[arg(long, default_value = "all")]
+    sheet: Worksheets,
+}
+
+// Thresholds for the balance and plausibility rules.
+#[derive(Args, Debug)]
+struct RuleArgs {
+    /// Largest allowed energy balance residual, in W
+    #[arg(long, value_name = "WATTS", default_value = "100")]
+    tolerance: u32,
+
+    /// Identical consecutive readings after which values count as frozen, or 0
+    /// to disable the rule
+    #[arg(long, value_name = "READINGS", default_value = "6")]
+    frozen: usize,
+
+    /// Largest allowed state of charge change between readings, in points
+    #[arg(long, value_name = "PERCENT", default_value = "20")]
+    soc_jump: u8,
+}
+
+impl RuleArgs {
+    fn rules(&self) -> Rules {
+        Rules::new(self.tolerance, self.frozen, self.soc_jump)
+    }
+}
+
+// How gaps between readings are detected and handled.
+#[derive(Args, Debug)]
+struct GapArgs {
+    /// Nominal time between readings, in minutes
+    #[arg(long, value_name = "MINUTES", default_value = "5")]
+    interval: i64,
+
+    /// Time between readings above which they count as a gap, in minutes
+    #[arg(long, value_name = "MINUTES", default_value = "15")]
+    gap_threshold: i64,
+
+    /// How the time missing in a gap is accounted for
+    #[arg(long, value_enum, default_value_t = GapFill::Drop)]
+    gap_fill: GapFill,
+}
+
+impl GapArgs {
+    fn options(&self) -> anyhow::Result<GapOptions> {
+        GapOptions::new(
+            Duration::minutes(self.interval),
+            Duration::minutes(self.gap_threshold),
+            self.gap_fill,
+        )
+    }
+}
+
+impl DataArgs {
+    fn load(
+        self,
+        period: Period,
+        investments: Investments,
+        limit: usize,
+    ) -> anyhow::Result<SolarData> {
+        let tariff = match self.tariff {
+            Some(path) => Tariff::from_file(path)?,
+            None => Tariff::default(),
+        };
+
+        let data = SolarData::from_folder(
+            self.path.unwrap_or_default(),
+            period,
+            investments,
+            limit,
+            tariff,
+            LoadOptions::new(
+                self.timezone,
+                self.exclude_invalid.then(|| self.rules.rules()),
+                self.gaps.options()?,
+                self.precedence,
+                self.integration,
+                self.sheet,
+            ),
+        )?;
+
+        // Every subcommand reports the merge, on stderr so as not to mix
+        // with its own output.
+        if data.duplicates().dropped() > 0 {
+            eprintln!("{}", data.duplicates());
+        }
+
+        for (sheet, reason) in data.skipped_sheets() {
+            eprintln!("Skipped {}: {reason}", sheet.display());
+        }
+
+        Ok(data.between(self.from, self.to))
+    }
+}
+
+// The capital cost of the system, as a lump sum or an investments file.
+#[derive(Args, Debug)]
+struct CostArgs {
+    /// Setup cost, paid before the records start
+    #[arg(short, long, default_value = "11000")]
+    cost: f64,
+
+    /// TOML or JSON file of dated costs and grants, replacing --cost
+    #[arg(long, value_name = "FILE", conflicts_with = "cost")]
+    investments: Option<PathBuf>,
+}
+
+impl CostArgs {
+    fn load(self) -> anyhow::Result<Investments> {
+        match self.investments {
+            Some(path) => Investments::from_file(path),
+            None => Ok(Investments::lump_sum(self.cost)),
+        }
+    }
+}
+
+#[derive(Args, Debug)]
+struct ReportArgs {
+    #[command(flatten)]
+    data: DataArgs,
+
     #[arg(value_name = "OUTPUT", conflicts_with = "output_flag")]
     output_positional: Option<String>,
     #[arg(
@@ -14,21 +221,224 @@ struct Args {
     )]
     output_flag: Option<String>,
 
-    #[arg(short, long, value_enum, default_value_t = Period::Month, value_parser = clap::value_parser!(Period))]
+    /// Aggregation period: minute, hour, day, week, month, quarter, year or a
+    /// billing cycle as billing:<START>[:<MONTHS>]
+    #[arg(short, long, default_value = "month")]
     period: Period,
 
-    #[arg(short, long, default_value = "11000")]
-    cost: f64,
+    #[command(flatten)]
+    cost: CostArgs,
 
     #[arg(short, long, default_value = "12")]
     limit: usize,
 }
 
-fn main() -> anyhow::Result<()> {
-    let args = Args::parse();
-    let output = args.output_positional.or(args.output_flag);
+#[derive(Args, Debug)]
+struct CompareArgs {
+    #[command(flatten)]
+    data: DataArgs,
+
+    /// Candidate TOML or JSON tariff files
+    #[arg(long = "candidate", value_name = "FILE", required = true)]
+    candidates: Vec<PathBuf>,
+
+    /// Aggregation period: minute, hour, day, week, month, quarter, year or a
+    /// billing cycle as billing:<START>[:<MONTHS>]
+    #[arg(short, long, default_value = "month")]
+    period: Period,
+
+    #[arg(short, long, default_value = "12")]
+    limit: usize,
+}
+
+#[derive(Args, Debug)]
+struct ProfileArgs {
+    #[command(flatten)]
+    data: DataArgs,
+
+    #[arg(short, long, value_name = "OUTPUT")]
+    output: Option<String>,
+
+    #[arg(short, long, value_enum, default_value_t = Profile::Hour)]
+    by: Profile,
+}
+
+#[derive(Args, Debug)]
+struct SimulateArgs {
+    #[command(flatten)]
+    data: DataArgs,
+
+    /// Usable battery capacity in kWh
+    #[arg(long, value_name = "KWH", value_parser = positive)]
+    capacity: f64,
+
+    /// Maximum charging power in kW
+    #[arg(long, value_name = "KW", default_value = "2.5", value_parser = positive)]
+    max_charge: f64,
+
+    /// Maximum discharging power in kW
+    #[arg(long, value_name = "KW", default_value = "2.5", value_parser = positive)]
+    max_discharge: f64,
+
+    /// Round-trip efficiency as a percentage
+    #[arg(long, value_name = "PERCENT", default_value = "90")]
+    efficiency: f64,
+
+    /// Minimum state of charge as a percentage
+    #[arg(long, value_name = "PERCENT", default_value = "10")]
+    min_soc: f64,
+
+    /// Grid import in kW above which peak shaving discharges the battery
+    #[arg(long, value_name = "KW", default_value = "3")]
+    peak_limit: f64,
+
+    /// Installed cost of the battery
+    #[arg(long, value_name = "EUR", value_parser = positive)]
+    battery_cost: f64,
+
+    /// When the battery charges and discharges
+    #[arg(long, value_enum, default_value_t = Dispatch::SelfConsumption)]
+    dispatch: Dispatch,
+
+    /// Rank every dispatch strategy by cost instead of reporting one
+    #[arg(long, conflicts_with = "dispatch")]
+    compare: bool,
+
+    /// Aggregation period: minute, hour, day, week, month, quarter, year or a
+    /// billing cycle as billing:<START>[:<MONTHS>]
+    #[arg(short, long, default_value = "month")]
+    period: Period,
+
+    #[command(flatten)]
+    cost: CostArgs,
+
+    #[arg(short, long, default_value = "12")]
+    limit: usize,
+}
+
+#[derive(Args, Debug)]
+#[command(group(ArgGroup::new("size").required(true).args(["factor", "kwp"])))]
+struct ScaleArgs {
+    #[command(flatten)]
+    data: DataArgs,
+
+    /// Factor to scale the recorded production by
+    #[arg(long, value_parser = positive)]
+    factor: Option<f64>,
+
+    /// New array size in kWp, relative to --current-kwp
+    #[arg(long, value_name = "KWP", requires = "current_kwp", value_parser = positive)]
+    kwp: Option<f64>,
+
+    /// Size of the array that produced the records, in kWp
+    #[arg(long, value_name = "KWP", value_parser = positive)]
+    current_kwp: Option<f64>,
+
+    /// Cost of the extra panels
+    #[arg(long, value_name = "EUR", default_value = "0")]
+    extra_cost: f64,
+
+    /// Aggregation period: minute, hour, day, week, month, quarter, year or a
+    /// billing cycle as billing:<START>[:<MONTHS>]
+    #[arg(short, long, default_value = "month")]
+    period: Period,
+
+    #[command(flatten)]
+    cost: CostArgs,
+
+    #[arg(short, long, default_value = "12")]
+    limit: usize,
+}
+
+#[derive(Args, Debug)]
+struct FinanceArgs {
+    #[command(flatten)]
+    data: DataArgs,
+
+    #[arg(short, long, value_name = "OUTPUT")]
+    output: Option<String>,
+
+    #[command(flatten)]
+    cost: CostArgs,
+
+    /// Cost of money, as a percentage per year
+    #[arg(long, value_name = "PERCENT", default_value = "4")]
+    discount_rate: f64,
 
-    let data = SolarData::from_folder(args.path, args.period, args.cost, args.limit)?;
+    /// Electricity price rise, as a percentage per year
+    #[arg(long, value_name = "PERCENT", default_value = "3")]
+    escalation: f64,
+
+    /// Loss of panel output, as a percentage per year
+    #[arg(long, value_name = "PERCENT", default_value = "0.5")]
+    degradation: f64,
+
+    /// Maintenance cost per year
+    #[arg(long, value_name = "EUR", default_value = "0")]
+    maintenance: f64,
+
+    /// Year of the system's lifetime the inverter is replaced in
+    #[arg(long, value_name = "YEAR")]
+    inverter_year: Option<u32>,
+
+    /// Cost of replacing the inverter
+    #[arg(long, value_name = "EUR", default_value = "1500")]
+    inverter_cost: f64,
+
+    /// Number of years to project
+    #[arg(long, default_value = "25")]
+    years: u32,
+}
+
+#[derive(Args, Debug)]
+struct ProjectionArgs {
+    #[command(flatten)]
+    data: DataArgs,
+
+    #[arg(short, long, value_name = "OUTPUT")]
+    output: Option<String>,
+
+    #[command(flatten)]
+    cost: CostArgs,
+}
+
+#[derive(Args, Debug)]
+struct CheckArgs {
+    #[arg(required = true)]
+    path: Option<String>,
+
+    /// Only check readings on or after this date (YYYY-MM-DD)
+    #[arg(long, value_name = "DATE")]
+    from: Option<NaiveDate>,
+
+    /// Only check readings on or before this date (YYYY-MM-DD)
+    #[arg(long, value_name = "DATE")]
+    to: Option<NaiveDate>,
+
+    #[command(flatten)]
+    rules: RuleArgs,
+
+    /// Worksheet to read from Excel exports: a name, a position counting from
+    /// 1, or all
+    #[arg(long, default_value = "all")]
+    sheet: Worksheets,
+
+    #[arg(short, long, value_name = "OUTPUT")]
+    output: Option<String>,
+}
+
+#[derive(Args, Debug)]
+struct YieldArgs {
+    #[command(flatten)]
+    data: DataArgs,
+
+    #[arg(short, long, value_name = "OUTPUT")]
+    output: Option<String>,
+}
+
+fn report(args: ReportArgs) -> anyhow::Result<()> {
+    let output = args.output_positional.or(args.output_flag);
+    let data = args.data.load(args.period, args.cost.load()?, args.limit)?;
 
     if let Some(output) = output {
         data.write(output)?;
@@ -38,3 +448,167 @@ fn main() -> anyhow::Result<()> {
     println!("{}", data);
     Ok(())
 }
+
+fn compare(args: CompareArgs) -> anyhow::Result<()> {
+    let candidates = args
+        .candidates
+        .iter()
+        .map(Tariff::from_file)
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    let data = args
+        .data
+        .load(args.period, Investments::default(), args.limit)?;
+
+    println!("{}", data.compare(&candidates));
+    Ok(())
+}
+
+fn profile(args: ProfileArgs) -> anyhow::Result<()> {
+    let data = args
+        .data
+        .load(Period::default(), Investments::default(), 0)?;
+    let profile = data.profile(args.by);
+
+    if let Some(output) = args.output {
+        profile.write(output)?;
+        return Ok(());
+    }
+
+    println!("{profile}");
+    Ok(())
+}
+
+fn simulate(args: SimulateArgs) -> anyhow::Result<()> {
+    let battery = Battery::new(
+        args.capacity,
+        args.max_charge,
+        args.max_discharge,
+        args.efficiency,
+        args.min_soc,
+    )?
+    .with_peak_limit(args.peak_limit);
+
+    let data = args.data.load(args.period, args.cost.load()?, args.limit)?;
+
+    if args.compare {
+        println!(
+            "{}",
+            data.compare_dispatch(battery, Dispatch::value_variants())
+        );
+        return Ok(());
+    }
+
+    println!(
+        "{}",
+        data.simulate(battery, args.dispatch, args.battery_cost)
+    );
+    Ok(())
+}
+
+fn scale(args: ScaleArgs) -> anyhow::Result<()> {
+    let factor = match (args.factor, args.kwp, args.current_kwp) {
+        (Some(factor), _, _) => factor,
+        (None, Some(kwp), Some(current_kwp)) => kwp / current_kwp,
+        _ => anyhow::bail!("Either --factor or --kwp and --current-kwp must be given"),
+    };
+
+    let data = args.data.load(args.period, args.cost.load()?, args.limit)?;
+
+    println!("{}", data.scale(factor, args.extra_cost));
+    Ok(())
+}
+
+/// Parses a number greater than zero.
+fn positive(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>() {
+        Ok(value) if value > 0_f64 && value.is_finite() => Ok(value),
+        Ok(_) => Err(format!("{s} is not greater than zero")),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+fn finance(args: FinanceArgs) -> anyhow::Result<()> {
+    let assumptions = Assumptions::new(
+        args.discount_rate,
+        args.escalation,
+        args.degradation,
+        args.maintenance,
+        args.inverter_year,
+        args.inverter_cost,
+        args.years,
+    );
+
+    let data = args.data.load(Period::default(), args.cost.load()?, 0)?;
+    let analysis = data.finance(assumptions);
+
+    if let Some(output) = args.output {
+        analysis.write(output)?;
+        return Ok(());
+    }
+
+    println!("{analysis}");
+    Ok(())
+}
+
+fn projection(args: ProjectionArgs) -> anyhow::Result<()> {
+    let data = args.data.load(Period::default(), args.cost.load()?, 0)?;
+    let projection = data.projection();
+
+    if let Some(output) = args.output {
+        projection.write(output)?;
+        return Ok(());
+    }
+
+    println!("{projection}");
+    Ok(())
+}
+
+fn check(args: CheckArgs) -> anyhow::Result<()> {
+    let report = CheckReport::from_folder(
+        args.path.unwrap_or_default(),
+        args.rules.rules(),
+        args.from,
+        args.to,
+        &args.sheet,
+    )?;
+
+    if let Some(output) = args.output {
+        report.write(output)?;
+        return Ok(());
+    }
+
+    print!("{report}");
+    Ok(())
+}
+
+fn daily_yield(args: YieldArgs) -> anyhow::Result<()> {
+    let data = args
+        .data
+        .load(Period::default(), Investments::default(), 0)?;
+    let comparison = data.yield_comparison();
+
+    if let Some(output) = args.output {
+        comparison.write(output)?;
+        return Ok(());
+    }
+
+    print!("{comparison}");
+    Ok(())
+}
+
+fn main() -> anyhow::Result<()> {
+    let cli = Cli::parse();
+
+    match cli.command {
+        Some(Command::Compare(args)) => compare(args),
+        Some(Command::Profile(args)) => profile(args),
+        Some(Command::Simulate(args)) => simulate(args),
+        Some(Command::Scale(args)) => scale(args),
+        Some(Command::Finance(args)) => finance(args),
+        Some(Command::Projection(args)) => projection(args),
+        Some(Command::Check(args)) => check(args),
+        Some(Command::Yield(args)) => daily_yield(args),
+        None => report(cli.report),
+    }
+}
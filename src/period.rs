@@ -1,29 +1,106 @@
 use core::str::FromStr;
 
-use chrono::{DateTime, Utc};
-use clap::ValueEnum;
+use chrono::{DateTime, Datelike, Months, NaiveDate};
+use chrono_tz::Tz;
+use serde::Deserialize;
+
+/// A supplier billing cycle, starting on `start` and repeating every `months`
+/// months.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub struct BillingCycle {
+    pub start: NaiveDate,
+    #[serde(default = "BillingCycle::default_months")]
+    pub months: u32,
+}
+
+impl Default for BillingCycle {
+    /// Calendar months in pairs, January and February first.
+    #[inline]
+    fn default() -> Self {
+        Self {
+            start: NaiveDate::default(),
+            months: Self::DEFAULT_MONTHS,
+        }
+    }
+}
+
+impl BillingCycle {
+    /// The length of a billing cycle when none is given.
+    pub const DEFAULT_MONTHS: u32 = 2;
+
+    const fn default_months() -> u32 {
+        Self::DEFAULT_MONTHS
+    }
+
+    /// Returns the first day of the `index`th cycle after the anchor cycle.
+    fn nth_start(self, index: i32) -> Option<NaiveDate> {
+        let months = Months::new(index.unsigned_abs() * self.months);
+
+        if index < 0 {
+            self.start.checked_sub_months(months)
+        } else {
+            self.start.checked_add_months(months)
+        }
+    }
+
+    /// Returns the first and last day of the cycle containing `date`, or
+    /// `None` if the cycle is no months long.
+    #[must_use]
+    pub fn bounds(self, date: NaiveDate) -> Option<(NaiveDate, NaiveDate)> {
+        let month = |date: NaiveDate| Some(date.year() * 12 + i32::try_from(date.month0()).ok()?);
+        let length = i32::try_from(self.months)
+            .ok()
+            .filter(|months| *months > 0)?;
+
+        // A first guess, corrected below for cycles not starting on the 1st
+        let mut index = (month(date)? - month(self.start)?).div_euclid(length);
+
+        while self.nth_start(index)? > date {
+            index -= 1;
+        }
+
+        while self.nth_start(index + 1)? <= date {
+            index += 1;
+        }
+
+        Some((
+            self.nth_start(index)?,
+            self.nth_start(index + 1)?.pred_opt()?,
+        ))
+    }
+}
 
 #[non_exhaustive]
-#[derive(Default, Debug, Clone, Copy, ValueEnum)]
+#[derive(Default, Debug, Clone, Copy)]
 pub enum Period {
     Minute,
     Hour,
     Day,
+    /// An ISO 8601 week, starting on Monday.
+    Week,
     #[default]
     Month,
+    Quarter,
     Year,
+    BillingCycle(BillingCycle),
 }
 
 impl Period {
     #[must_use]
     #[inline]
-    pub fn key(&self, date: &DateTime<Utc>) -> String {
+    pub fn key(&self, date: &DateTime<Tz>) -> String {
         match *self {
             Self::Minute => format!("{}", date.format("%Y-%m-%d %H:%M")),
             Self::Hour => format!("{}", date.format("%Y-%m-%d %H")),
             Self::Day => format!("{}", date.format("%Y-%m-%d")),
+            Self::Week => format!("{}", date.format("%G-W%V")),
             Self::Month => format!("{}", date.format("%Y-%m")),
+            Self::Quarter => format!("{}-Q{}", date.year(), date.month0() / 3 + 1),
             Self::Year => format!("{}", date.format("%Y")),
+            Self::BillingCycle(cycle) => match cycle.bounds(date.date_naive()) {
+                Some((start, end)) => format!("{start} to {end}"),
+                None => format!("{}", date.format("%Y-%m-%d")),
+            },
         }
     }
 }
@@ -31,15 +108,110 @@ impl Period {
 impl FromStr for Period {
     type Err = String;
 
+    /// Parses a period name, or a billing cycle as `billing:<START>[:<MONTHS>]`,
+    /// e.g. `billing:2024-01-15:2`.
     #[inline]
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        match s.to_lowercase().as_str() {
-            "minute" | "minutes" => Ok(Self::Minute),
-            "hour" | "hours" => Ok(Self::Hour),
-            "day" | "days" => Ok(Self::Day),
-            "month" | "months" => Ok(Self::Month),
-            "year" | "years" => Ok(Self::Year),
+        let lowercase = s.to_lowercase();
+        let mut parts = lowercase.split(':');
+
+        match (parts.next(), parts.next(), parts.next(), parts.next()) {
+            (Some("minute" | "minutes"), None, None, None) => Ok(Self::Minute),
+            (Some("hour" | "hours"), None, None, None) => Ok(Self::Hour),
+            (Some("day" | "days"), None, None, None) => Ok(Self::Day),
+            (Some("week" | "weeks"), None, None, None) => Ok(Self::Week),
+            (Some("month" | "months"), None, None, None) => Ok(Self::Month),
+            (Some("quarter" | "quarters"), None, None, None) => Ok(Self::Quarter),
+            (Some("year" | "years"), None, None, None) => Ok(Self::Year),
+            (Some("billing" | "bill"), Some(start), months, None) => {
+                let start = start
+                    .parse::<NaiveDate>()
+                    .map_err(|e| format!("Invalid billing cycle start {start}: {e}"))?;
+
+                let months = match months {
+                    Some(months) => months
+                        .parse::<u32>()
+                        .ok()
+                        .filter(|months| *months > 0)
+                        .ok_or_else(|| format!("Invalid billing cycle length: {months}"))?,
+                    None => BillingCycle::DEFAULT_MONTHS,
+                };
+
+                Ok(Self::BillingCycle(BillingCycle { start, months }))
+            }
             _ => Err(format!("Invalid period: {s}")),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::{anyhow, ensure, Context};
+    use chrono::TimeZone;
+    use chrono_tz::Europe::Dublin;
+
+    #[test]
+    fn test_week_and_quarter_keys() -> anyhow::Result<()> {
+        let key = |period: &str, (year, month, day)| -> anyhow::Result<String> {
+            let date = Dublin
+                .with_ymd_and_hms(year, month, day, 12, 0, 0)
+                .single()
+                .context("Failed to create DateTime<Tz> value")?;
+
+            Ok(Period::from_str(period).map_err(|e| anyhow!(e))?.key(&date))
+        };
+
+        ensure!(key("week", (2024, 2, 14))? == "2024-W07");
+        ensure!(key("week", (2024, 12, 30))? == "2025-W01");
+        ensure!(key("quarter", (2024, 3, 31))? == "2024-Q1");
+        ensure!(key("quarters", (2024, 10, 1))? == "2024-Q4");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_billing_cycle_key() -> anyhow::Result<()> {
+        let period = Period::from_str("billing:2024-01-15").map_err(|e| anyhow!(e))?;
+
+        let key = |year, month, day| -> anyhow::Result<String> {
+            let date = Dublin
+                .with_ymd_and_hms(year, month, day, 12, 0, 0)
+                .single()
+                .context("Failed to create DateTime<Tz> value")?;
+
+            Ok(period.key(&date))
+        };
+
+        ensure!(key(2024, 1, 15)? == "2024-01-15 to 2024-03-14");
+        ensure!(key(2024, 3, 14)? == "2024-01-15 to 2024-03-14");
+        ensure!(key(2024, 3, 15)? == "2024-03-15 to 2024-05-14");
+        ensure!(key(2023, 12, 1)? == "2023-11-15 to 2024-01-14");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_billing_cycle_end_of_month() -> anyhow::Result<()> {
+        let cycle = BillingCycle {
+            start: NaiveDate::from_ymd_opt(2024, 1, 31).context("Invalid date")?,
+            months: 1,
+        };
+
+        let date = NaiveDate::from_ymd_opt(2024, 3, 1).context("Invalid date")?;
+        let (start, end) = cycle.bounds(date).context("No bounds")?;
+
+        ensure!(start.to_string() == "2024-02-29" && end.to_string() == "2024-03-30");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_invalid_billing_cycle() -> anyhow::Result<()> {
+        ensure!(Period::from_str("billing").is_err());
+        ensure!(Period::from_str("billing:2024-01-15:0").is_err());
+        ensure!(Period::from_str("billing:15/01/2024").is_err());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,186 @@
+use core::fmt::{self, Display, Formatter};
+use std::{collections::HashSet, path::Path};
+
+use chrono::{DateTime, Datelike, Timelike};
+use chrono_tz::Tz;
+use clap::ValueEnum;
+use parsers::csv;
+use serde::Serialize;
+use tabled::{
+    settings::{object::Cell, Modify, Style},
+    Table, Tabled,
+};
+
+use crate::{formatting::watt_hour_to_string, solar_record::SolarRecord};
+
+/// How records are bucketed when building an average day.
+#[non_exhaustive]
+#[derive(Default, Debug, Clone, Copy, ValueEnum)]
+pub enum Profile {
+    /// Each hour of the day.
+    #[default]
+    Hour,
+    /// Each 15-minute slot of the day.
+    Slot,
+    /// Each day of the week.
+    Weekday,
+    /// Each month of the year.
+    Month,
+}
+
+impl Profile {
+    /// Returns the name of the buckets, to head the profile with.
+    #[must_use]
+    #[inline]
+    pub fn label(&self) -> &'static str {
+        match *self {
+            Self::Hour => "Hour",
+            Self::Slot => "Slot",
+            Self::Weekday => "Weekday",
+            Self::Month => "Month",
+        }
+    }
+
+    /// Returns the sort index and label of the bucket `date` falls in.
+    #[must_use]
+    #[inline]
+    pub fn bucket(&self, date: &DateTime<Tz>) -> (u32, String) {
+        match *self {
+            Self::Hour => (date.hour(), format!("{}", date.format("%H:00"))),
+            Self::Slot => {
+                let minute = date.minute() / 15 * 15;
+                (
+                    date.hour() * 60 + minute,
+                    format!("{:02}:{minute:02}", date.hour()),
+                )
+            }
+            Self::Weekday => (
+                date.weekday().num_days_from_monday(),
+                format!("{}", date.format("%a")),
+            ),
+            Self::Month => (date.month0(), format!("{}", date.format("%b"))),
+        }
+    }
+}
+
+/// The average energy flows, in Wh per day, of one bucket of a profile.
+#[derive(Debug, Tabled, Serialize)]
+pub struct ProfileRecord {
+    #[tabled(rename = "Slot")]
+    key: String,
+    #[tabled(rename = "Days")]
+    days: usize,
+    #[tabled(rename = "Production", display_with = "watt_hour_to_string")]
+    production: f64,
+    #[tabled(rename = "Consumption", display_with = "watt_hour_to_string")]
+    consumption: f64,
+    #[tabled(rename = "Purchased", display_with = "watt_hour_to_string")]
+    purchased: f64,
+    #[tabled(rename = "Feed In", display_with = "watt_hour_to_string")]
+    feed_in: f64,
+}
+
+impl ProfileRecord {
+    /// Averages `records` over the number of distinct days they cover.
+    #[must_use]
+    pub(crate) fn new(records: &[SolarRecord], key: &str) -> Self {
+        let days = records
+            .iter()
+            .map(|r| r.date_time().date_naive())
+            .collect::<HashSet<_>>()
+            .len();
+
+        let mean = |metric: fn(&SolarRecord) -> f64| {
+            records.iter().map(metric).sum::<f64>() / days.max(1) as f64
+        };
+
+        Self {
+            key: key.to_owned(),
+            days,
+            production: mean(SolarRecord::production),
+            consumption: mean(SolarRecord::consumption),
+            purchased: mean(SolarRecord::purchased),
+            feed_in: mean(SolarRecord::feed_in),
+        }
+    }
+}
+
+/// The shape of an average day, bucketed by time of day, weekday or month.
+#[derive(Debug)]
+pub struct DailyProfile {
+    profile: Profile,
+    records: Vec<ProfileRecord>,
+}
+
+impl DailyProfile {
+    #[must_use]
+    pub(crate) fn new(profile: Profile, records: Vec<ProfileRecord>) -> Self {
+        Self { profile, records }
+    }
+
+    /// Writes the profile to a CSV file.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the file cannot be written.
+    #[inline]
+    pub fn write<P: AsRef<Path>>(&self, path: P) -> anyhow::Result<()> {
+        csv::write(path, &self.records)
+    }
+}
+
+impl Display for DailyProfile {
+    #[inline]
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let mut table = Table::new(&self.records);
+        table
+            .with(Style::rounded())
+            .with(Modify::new(Cell::new(0, 0)).with(self.profile.label().to_owned()));
+
+        writeln!(f, "{table}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::{ensure, Context};
+    use chrono::TimeZone;
+    use chrono_tz::Europe::Dublin;
+
+    #[test]
+    fn test_bucket() -> anyhow::Result<()> {
+        let date = Dublin
+            .with_ymd_and_hms(2024, 6, 1, 13, 40, 0)
+            .single()
+            .context("Failed to create DateTime<Tz> value")?;
+
+        ensure!(Profile::Hour.bucket(&date) == (13, "13:00".to_owned()));
+        ensure!(Profile::Slot.bucket(&date) == (13 * 60 + 30, "13:30".to_owned()));
+        ensure!(Profile::Weekday.bucket(&date) == (5, "Sat".to_owned()));
+        ensure!(Profile::Month.bucket(&date) == (5, "Jun".to_owned()));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_profile_record_average() -> anyhow::Result<()> {
+        // The 13:00 records of two days, a day apart.
+        let records = SolarRecord::hourly("2024-06-01 13:00", 25, |hour| {
+            (if hour == 0 { 1000 } else { 3000 }, 0, 0, 0, 0)
+        })?
+        .into_iter()
+        .step_by(24)
+        .collect::<Vec<_>>();
+
+        let record = ProfileRecord::new(&records, "13:00");
+
+        ensure!(record.days == 2);
+        ensure!((record.production - 2000_f64).abs() < f64::EPSILON);
+
+        let table = DailyProfile::new(Profile::Weekday, vec![record]).to_string();
+        ensure!(table.contains("Weekday") && !table.contains("Slot"));
+
+        Ok(())
+    }
+}
@@ -1,106 +1,253 @@
-use std::cmp::Ordering;
-
-use chrono::{DateTime, TimeZone, Timelike, Utc};
-use strum::IntoEnumIterator;
-use strum_macros::EnumIter;
-
-#[derive(Debug, EnumIter, PartialEq, Eq)]
-pub(crate) enum Rate {
-    ElectricIrelandV0,
-    ElectricIrelandV1,
-    ElectricIrelandV2,
-    EnergiaV0,
+use chrono::{DateTime, Datelike, NaiveDate, Timelike, Weekday};
+use chrono_tz::Tz;
+use serde::Deserialize;
+
+/// A day, or group of days, on which a band applies.
+#[derive(Debug, Clone, Copy, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum Day {
+    #[serde(alias = "mon")]
+    Monday,
+    #[serde(alias = "tue")]
+    Tuesday,
+    #[serde(alias = "wed")]
+    Wednesday,
+    #[serde(alias = "thu")]
+    Thursday,
+    #[serde(alias = "fri")]
+    Friday,
+    #[serde(alias = "sat")]
+    Saturday,
+    #[serde(alias = "sun")]
+    Sunday,
+    /// Monday to Friday, excluding public holidays.
+    Weekdays,
+    /// Saturday and Sunday.
+    Weekends,
+    /// The public holidays listed in the tariff file.
+    Holidays,
 }
 
-impl Rate {
-    fn value(&self) -> DateTime<Utc> {
-        match *self {
-            Rate::ElectricIrelandV0 => Utc.with_ymd_and_hms(1, 1, 1, 0, 0, 0).unwrap(),
-            Rate::ElectricIrelandV1 => Utc.with_ymd_and_hms(2024, 3, 1, 0, 0, 0).unwrap(),
-            Rate::ElectricIrelandV2 => Utc.with_ymd_and_hms(2024, 11, 1, 0, 0, 0).unwrap(),
-            Rate::EnergiaV0 => Utc.with_ymd_and_hms(2025, 1, 15, 0, 0, 0).unwrap(),
+impl Day {
+    fn matches(self, weekday: Weekday, holiday: bool) -> bool {
+        match self {
+            Self::Monday => weekday == Weekday::Mon,
+            Self::Tuesday => weekday == Weekday::Tue,
+            Self::Wednesday => weekday == Weekday::Wed,
+            Self::Thursday => weekday == Weekday::Thu,
+            Self::Friday => weekday == Weekday::Fri,
+            Self::Saturday => weekday == Weekday::Sat,
+            Self::Sunday => weekday == Weekday::Sun,
+            Self::Weekdays => !holiday && weekday.num_days_from_monday() < 5,
+            Self::Weekends => weekday.num_days_from_monday() >= 5,
+            Self::Holidays => holiday,
         }
     }
+}
 
-    pub fn standing_charge(&self) -> f64 {
+/// A meteorological season, used to restrict a band to part of the year.
+#[derive(Debug, Clone, Copy, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum Season {
+    /// December to February.
+    Winter,
+    /// March to May.
+    Spring,
+    /// June to August.
+    Summer,
+    /// September to November.
+    Autumn,
+}
+
+impl Season {
+    fn contains(self, month: u32) -> bool {
         match self {
-            Rate::ElectricIrelandV0 => 0.9976_f64,
-            Rate::ElectricIrelandV1 => 0.9976_f64,
-            Rate::ElectricIrelandV2 => 0.8259_f64,
-            Rate::EnergiaV0 => 0.6482739726_f64,
+            Self::Winter => matches!(month, 12 | 1 | 2),
+            Self::Spring => (3..=5).contains(&month),
+            Self::Summer => (6..=8).contains(&month),
+            Self::Autumn => (9..=11).contains(&month),
         }
     }
+}
 
-    pub fn feed_in(&self) -> f64 {
-        (match self {
-            Rate::ElectricIrelandV0 => 0.21_f64,
-            Rate::ElectricIrelandV1 => 0.21_f64,
-            Rate::ElectricIrelandV2 => 0.195_f64,
-            Rate::EnergiaV0 => 0.20_f64,
-        } / 1000_f64)
-    }
+/// The local time at which a band is looked up.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Slot {
+    pub hour: u32,
+    pub weekday: Weekday,
+    pub month: u32,
+    pub holiday: bool,
+}
+
+/// A unit rate applying to a set of hours of the day, optionally restricted
+/// to certain days, months or seasons.
+///
+/// Empty restrictions apply all year round.
+#[derive(Debug, Clone, Deserialize, PartialEq)]
+pub(crate) struct Band {
+    /// The name of the band, e.g. "Night" or "Peak".
+    pub name: String,
+    /// The hours of the day (0-23) during which the band applies.
+    pub hours: Vec<u32>,
+    /// The days on which the band applies.
+    #[serde(default)]
+    pub days: Vec<Day>,
+    /// The months (1-12) in which the band applies.
+    #[serde(default)]
+    pub months: Vec<u32>,
+    /// The seasons in which the band applies.
+    #[serde(default)]
+    pub seasons: Vec<Season>,
+    /// The rate, in euro per kWh.
+    pub rate: f64,
+}
+
+impl Band {
+    fn matches(&self, slot: Slot) -> bool {
+        let Slot {
+            hour,
+            weekday,
+            month,
+            holiday,
+        } = slot;
 
-    fn evaluate(&self, date: DateTime<Utc>) -> f64 {
-        (match self {
-            Rate::ElectricIrelandV0 => match date.hour() {
-                23 | 0..=1 | 4..=7 => 0.2092_f64,
-                2..=3 => 0.1228_f64,
-                8..=22 => 0.4008_f64,
-                _ => unreachable!("Invalid hour"),
-            },
-            Rate::ElectricIrelandV1 => match date.hour() {
-                23 | 0..=1 | 4..=7 => 0.1783_f64,
-                2..=3 => 0.1047_f64,
-                8..=22 => 0.3615_f64,
-                _ => unreachable!("Invalid hour"),
-            },
-            Rate::ElectricIrelandV2 => match date.hour() {
-                23 | 0..=1 | 4..=7 => 0.1783_f64,
-                2..=3 => 0.1047_f64,
-                8..=22 => 0.3615_f64,
-                _ => unreachable!("Invalid hour"),
-            },
-            Rate::EnergiaV0 => match date.hour() {
-                23 | 0..=7 => 0.1349_f64,
-                8..=16 | 19..=22 => 0.2521_f64,
-                17..=18 => 0.2642_f64,
-                _ => unreachable!("Invalid hour"),
-            },
-        } / 1000_f64)
+        self.hours.contains(&hour)
+            && (self.days.is_empty() || self.days.iter().any(|day| day.matches(weekday, holiday)))
+            && (self.months.is_empty() || self.months.contains(&month))
+            && (self.seasons.is_empty() || self.seasons.iter().any(|season| season.contains(month)))
     }
 
-    pub fn cost(&self, consumption: i32, date: DateTime<Utc>) -> f64 {
-        if consumption < 0 {
-            return self.feed_in() * consumption as f64 * self.evaluate(date);
+    /// Checks that the band's hours are in 0-23 and its months in 1-12.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` naming the band and the first value out of range.
+    pub(crate) fn validate(&self) -> anyhow::Result<()> {
+        if let Some(hour) = self.hours.iter().find(|&&hour| hour >= 24) {
+            anyhow::bail!("Band {} has hour {hour} outside 0-23", self.name);
+        }
+
+        if let Some(month) = self.months.iter().find(|month| !(1..=12).contains(*month)) {
+            anyhow::bail!("Band {} has month {month} outside 1-12", self.name);
         }
 
-        consumption as f64 * self.evaluate(date)
+        Ok(())
     }
-}
 
-impl Ord for Rate {
-    fn cmp(&self, other: &Self) -> Ordering {
-        self.value().cmp(&other.value())
+    /// Returns the first of `bands` matching `slot`.
+    fn find(bands: &[Self], slot: Slot) -> Option<&Self> {
+        bands.iter().find(|band| band.matches(slot))
     }
 }
 
-impl PartialOrd for Rate {
-    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        Some(self.cmp(other))
-    }
+/// The rate paid for electricity exported to the grid.
+#[derive(Debug, Clone, Deserialize, PartialEq)]
+#[serde(untagged)]
+pub(crate) enum ExportRate {
+    /// A single rate, in euro per kWh, paid at all times.
+    Flat(f64),
+    /// Time-of-use bands, which together must cover every hour of every day.
+    TimeOfUse(Vec<Band>),
 }
 
-impl From<DateTime<Utc>> for Rate {
-    fn from(date: DateTime<Utc>) -> Self {
-        return Rate::iter()
-            .filter(|version| date > version.value())
-            .max()
-            .unwrap_or_default();
-    }
+/// A version of a tariff, in effect from its start date until the start date
+/// of the next version.
+#[derive(Debug, Clone, Deserialize, PartialEq)]
+pub(crate) struct Rate {
+    /// The name of the tariff version, e.g. "Electric Ireland V1".
+    pub name: String,
+    /// The first day on which the version applies.
+    pub start: NaiveDate,
+    /// The standing charge, in euro per day.
+    standing_charge: f64,
+    /// The PSO levy, in euro per month.
+    #[serde(default)]
+    pso_levy: f64,
+    /// The VAT rate applied to unit rates, standing charges and levies, e.g.
+    /// 0.09 for 9%. Leave unset if the rates already include VAT.
+    #[serde(default)]
+    vat: f64,
+    /// The export rate.
+    #[serde(alias = "feed_in")]
+    export: ExportRate,
+    /// The unit rate bands, which together must cover every hour of every
+    /// day. The first matching band applies.
+    pub bands: Vec<Band>,
+    /// The public holidays of the tariff, shared from the tariff file.
+    #[serde(skip)]
+    pub holidays: Vec<NaiveDate>,
 }
 
-impl Default for Rate {
-    fn default() -> Self {
-        Rate::iter().last().unwrap_or(Rate::ElectricIrelandV0)
+impl Rate {
+    pub fn standing_charge(&self) -> f64 {
+        self.standing_charge
+    }
+
+    /// Returns the PSO levy, in euro per month.
+    pub fn pso_levy(&self) -> f64 {
+        self.pso_levy
+    }
+
+    /// Adds VAT to an amount in euro.
+    pub fn with_vat(&self, amount: f64) -> f64 {
+        amount * (1_f64 + self.vat)
+    }
+
+    /// Returns the slot the local time of `date` falls in.
+    pub(crate) fn slot(&self, date: DateTime<Tz>) -> Slot {
+        Slot {
+            hour: date.hour(),
+            weekday: date.weekday(),
+            month: date.month(),
+            holiday: self.holidays.contains(&date.date_naive()),
+        }
+    }
+
+    /// Returns the unit rate band in effect during `slot`.
+    pub(crate) fn band(&self, slot: Slot) -> Option<&Band> {
+        Band::find(&self.bands, slot)
+    }
+
+    /// Returns the cheapest and dearest unit rates, in euro per kWh, over the
+    /// day `slot` falls on.
+    pub(crate) fn daily_range(&self, slot: Slot) -> (f64, f64) {
+        (0..24)
+            .filter_map(|hour| self.band(Slot { hour, ..slot }))
+            .fold(
+                (f64::INFINITY, f64::NEG_INFINITY),
+                |(cheapest, dearest), band| (cheapest.min(band.rate), dearest.max(band.rate)),
+            )
+    }
+
+    /// Returns the unit rate bands followed by the export bands, if any.
+    pub(crate) fn all_bands(&self) -> impl Iterator<Item = &Band> {
+        let export = match self.export {
+            ExportRate::Flat(_) => &[][..],
+            ExportRate::TimeOfUse(ref bands) => bands.as_slice(),
+        };
+
+        self.bands.iter().chain(export)
+    }
+
+    /// Returns the export rate, in euro per kWh, in effect during `slot`.
+    pub(crate) fn export_rate(&self, slot: Slot) -> Option<f64> {
+        match self.export {
+            ExportRate::Flat(rate) => Some(rate),
+            ExportRate::TimeOfUse(ref bands) => Band::find(bands, slot).map(|band| band.rate),
+        }
+    }
+
+    /// Returns the cost, in euro, of importing `energy` Wh at `date`.
+    pub fn import_cost(&self, energy: f64, date: DateTime<Tz>) -> f64 {
+        let rate = self.band(self.slot(date)).map_or(0_f64, |band| band.rate);
+
+        energy * rate / 1000_f64
+    }
+
+    /// Returns the credit, in euro, for exporting `energy` Wh at `date`.
+    pub fn export_credit(&self, energy: f64, date: DateTime<Tz>) -> f64 {
+        let rate = self.export_rate(self.slot(date)).unwrap_or(0_f64);
+
+        energy * rate / 1000_f64
     }
 }
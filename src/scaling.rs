@@ -0,0 +1,84 @@
+use core::fmt::{self, Display, Formatter};
+
+use crate::{
+    formatting::{euro_to_string, watt_hour_to_string},
+    solar_data::SolarData,
+};
+
+/// The recorded data replayed with the PV array at its current size and
+/// scaled to a new one.
+#[derive(Debug)]
+pub struct ArrayScaling {
+    factor: f64,
+    cost: f64,
+    baseline: SolarData,
+    scaled: SolarData,
+}
+
+impl ArrayScaling {
+    #[must_use]
+    pub(crate) fn new(factor: f64, cost: f64, baseline: SolarData, scaled: SolarData) -> Self {
+        Self {
+            factor,
+            cost,
+            baseline,
+            scaled,
+        }
+    }
+
+    /// Savings the larger array adds over the recorded period.
+    #[must_use]
+    #[inline]
+    pub fn savings_change(&self) -> f64 {
+        self.scaled.savings() - self.baseline.savings()
+    }
+
+    /// Savings the larger array adds per year, extrapolated from the recorded
+    /// days.
+    #[must_use]
+    #[inline]
+    pub fn annual_savings_change(&self) -> f64 {
+        self.savings_change() / self.baseline.days().max(1) as f64 * 365_f64
+    }
+
+    /// Extra energy, in Wh, fed into the grid over the recorded period.
+    #[must_use]
+    #[inline]
+    pub fn feed_in_change(&self) -> f64 {
+        self.scaled.feed_in() - self.baseline.feed_in()
+    }
+
+    /// Days by which the expected payoff date moves, or `None` if either
+    /// array never pays off.
+    #[must_use]
+    #[inline]
+    pub fn payoff_change(&self) -> Option<i64> {
+        Some(
+            self.scaled
+                .remaining_days()?
+                .saturating_sub(self.baseline.remaining_days()?),
+        )
+    }
+}
+
+impl Display for ArrayScaling {
+    #[inline]
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let feed_in_change = self.feed_in_change();
+        let sign = if feed_in_change < 0_f64 { "-" } else { "+" };
+        let payoff_change = self
+            .payoff_change()
+            .map_or_else(|| "n/a".to_owned(), |days| format!("{days:+} days"));
+
+        write!(
+            f,
+            "{}\nArray Scale: x{:.2}\nExtra Cost: {}\nSavings Change: {} ({} per year)\nFeed In Change: {sign}{}\nPayoff Change: {payoff_change}\n",
+            self.scaled,
+            self.factor,
+            euro_to_string(&self.cost),
+            euro_to_string(&self.savings_change()),
+            euro_to_string(&self.annual_savings_change()),
+            watt_hour_to_string(&feed_in_change.abs()),
+        )
+    }
+}
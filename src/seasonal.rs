@@ -0,0 +1,242 @@
+use core::fmt::{self, Display, Formatter};
+use std::path::Path;
+
+use chrono::{Datelike, Duration, NaiveDate};
+use parsers::csv;
+use serde::Serialize;
+use tabled::{settings::Style, Table, Tabled};
+
+use crate::formatting::euro_to_string;
+
+/// How far ahead a projection looks before giving up on paying off.
+const HORIZON_DAYS: i64 = 100 * 365;
+
+const MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+
+/// The mean daily savings in one calendar month of the history.
+#[derive(Debug, Clone, Tabled, Serialize)]
+pub struct MonthlySavings {
+    #[tabled(rename = "Month")]
+    month: &'static str,
+    #[tabled(rename = "Days")]
+    days: usize,
+    #[tabled(rename = "Mean Daily Savings", display_with = "euro_to_string")]
+    savings: f64,
+}
+
+/// The mean daily savings for each calendar month, learned from the history.
+/// Months without any history fall back to the mean over all days.
+#[derive(Debug)]
+pub struct SeasonalProfile {
+    months: Vec<MonthlySavings>,
+}
+
+impl SeasonalProfile {
+    /// Learns the profile from the savings of each recorded day.
+    #[must_use]
+    pub(crate) fn new(daily: &[(NaiveDate, f64)]) -> Self {
+        let overall =
+            daily.iter().map(|(_, savings)| savings).sum::<f64>() / daily.len().max(1) as f64;
+
+        let months = MONTHS
+            .iter()
+            .zip(1..)
+            .map(|(&month, number)| {
+                let savings = daily
+                    .iter()
+                    .filter(|(date, _)| date.month() == number)
+                    .map(|(_, savings)| *savings)
+                    .collect::<Vec<_>>();
+
+                MonthlySavings {
+                    month,
+                    days: savings.len(),
+                    savings: if savings.is_empty() {
+                        overall
+                    } else {
+                        savings.iter().sum::<f64>() / savings.len() as f64
+                    },
+                }
+            })
+            .collect();
+
+        Self { months }
+    }
+
+    /// Returns the expected savings on `date`.
+    #[must_use]
+    #[inline]
+    pub fn daily_savings(&self, date: NaiveDate) -> f64 {
+        self.months
+            .get(date.month0() as usize)
+            .map_or(0_f64, |month| month.savings)
+    }
+
+    /// Returns the number of days from `start` until the projected savings
+    /// cover `remaining`, or `None` if they never do.
+    #[must_use]
+    #[inline]
+    pub fn remaining_days(&self, start: NaiveDate, remaining: f64) -> Option<i64> {
+        let mut remaining = remaining;
+
+        (0..=HORIZON_DAYS).find(|&day| {
+            if remaining <= 0_f64 {
+                return true;
+            }
+
+            remaining -= self.daily_savings(start + Duration::days(day));
+            false
+        })
+    }
+
+    /// Projects the savings month by month from `start`, on top of `saved` to
+    /// date, until they cover `setup_cost`.
+    #[must_use]
+    pub(crate) fn project(self, start: NaiveDate, saved: f64, setup_cost: f64) -> Projection {
+        let mut points = Vec::new();
+        let mut cumulative = saved;
+        let mut savings = 0_f64;
+
+        for date in (0..HORIZON_DAYS).map(|day| start + Duration::days(day)) {
+            if cumulative >= setup_cost {
+                break;
+            }
+
+            savings += self.daily_savings(date);
+            cumulative += self.daily_savings(date);
+
+            let month_end = (date + Duration::days(1)).month() != date.month();
+
+            if month_end || cumulative >= setup_cost {
+                points.push(ProjectionPoint {
+                    month: date.format("%Y-%m").to_string(),
+                    savings,
+                    cumulative,
+                    remaining: (setup_cost - cumulative).max(0_f64),
+                });
+                savings = 0_f64;
+            }
+        }
+
+        let payoff_date = self
+            .remaining_days(start, setup_cost - saved)
+            .map(|days| start + Duration::days(days));
+
+        Projection {
+            profile: self,
+            points,
+            payoff_date,
+        }
+    }
+}
+
+/// The projected savings of one month.
+#[derive(Debug, Tabled, Serialize)]
+pub struct ProjectionPoint {
+    #[tabled(rename = "Month")]
+    month: String,
+    #[tabled(rename = "Savings", display_with = "euro_to_string")]
+    savings: f64,
+    /// Savings to date plus the projected savings so far.
+    #[tabled(rename = "Cumulative Savings", display_with = "euro_to_string")]
+    cumulative: f64,
+    #[tabled(rename = "Remaining Balance", display_with = "euro_to_string")]
+    remaining: f64,
+}
+
+/// The cumulative savings projected from a seasonal profile until payoff.
+#[derive(Debug)]
+pub struct Projection {
+    profile: SeasonalProfile,
+    points: Vec<ProjectionPoint>,
+    payoff_date: Option<NaiveDate>,
+}
+
+impl Projection {
+    /// Writes the projected curve to a CSV file.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the file cannot be written.
+    #[inline]
+    pub fn write<P: AsRef<Path>>(&self, path: P) -> anyhow::Result<()> {
+        csv::write(path, &self.points)
+    }
+}
+
+impl Display for Projection {
+    #[inline]
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let mut profile = Table::new(&self.profile.months);
+        profile.with(Style::rounded());
+
+        let mut points = Table::new(&self.points);
+        points.with(Style::rounded());
+
+        let payoff_date = self
+            .payoff_date
+            .map_or_else(|| "never".to_owned(), |date| date.to_string());
+
+        write!(
+            f,
+            "{profile}\n{points}\nExpected Payoff Date: {payoff_date}\n"
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::{ensure, Context};
+
+    fn profile() -> anyhow::Result<SeasonalProfile> {
+        let daily = (1..=31)
+            .map(|day| -> anyhow::Result<(NaiveDate, f64)> {
+                Ok((
+                    NaiveDate::from_ymd_opt(2024, 1, day).context("Invalid date")?,
+                    1_f64,
+                ))
+            })
+            .chain((1..=31).map(|day| {
+                Ok((
+                    NaiveDate::from_ymd_opt(2024, 7, day).context("Invalid date")?,
+                    3_f64,
+                ))
+            }))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+
+        Ok(SeasonalProfile::new(&daily))
+    }
+
+    #[test]
+    fn test_profile() -> anyhow::Result<()> {
+        let profile = profile()?;
+
+        let date = |month| NaiveDate::from_ymd_opt(2030, month, 15).context("Invalid date");
+        ensure!((profile.daily_savings(date(1)?) - 1_f64).abs() < f64::EPSILON);
+        ensure!((profile.daily_savings(date(7)?) - 3_f64).abs() < f64::EPSILON);
+        ensure!((profile.daily_savings(date(4)?) - 2_f64).abs() < f64::EPSILON);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_project() -> anyhow::Result<()> {
+        let start = NaiveDate::from_ymd_opt(2025, 1, 1).context("Invalid date")?;
+        let profile = profile()?;
+
+        ensure!(profile.remaining_days(start, 31_f64) == Some(31));
+        ensure!(profile.remaining_days(start, 0_f64) == Some(0));
+
+        // 31 in January, then 2 a day in February.
+        let projection = profile.project(start, 10_f64, 51_f64);
+        ensure!(projection.points.len() == 2);
+        ensure!((projection.points[1].savings - 10_f64).abs() < f64::EPSILON);
+        ensure!(projection.points[1].remaining.abs() < f64::EPSILON);
+        ensure!(projection.payoff_date == NaiveDate::from_ymd_opt(2025, 2, 6));
+
+        Ok(())
+    }
+}
@@ -1,30 +1,115 @@
 use core::fmt::{self, Display, Formatter};
-use std::path::Path;
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    path::{Path, PathBuf},
+};
 
-use parsers::{csv, parse_spreadsheets_from_folder};
+use parsers::{csv, Worksheets};
 
-use chrono::{DateTime, NaiveDate, Utc};
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+use chrono_tz::Tz;
 use itertools::Itertools;
 use tabled::{
     builder::Builder,
     settings::{Concat, Style},
-    Table,
+    Table, Tabled,
 };
 
 use crate::{
     aggregate_solar_record::AggregateSolarRecord,
-    formatting::{euro_to_string, watt_hour_to_string},
+    battery::{Battery, BatterySimulation, Dispatch},
+    check::Rules,
+    comparison::Comparison,
+    dedup::{deduplicate, Deduplication, Precedence},
+    energy_record::{daily_totals, report_records},
+    export::Exports,
+    finance::{Assumptions, FinancialAnalysis},
+    gap::{Gap, GapOptions},
+    integration::{Integration, YieldComparison},
+    investment::{Investment, Investments, Payback},
     period::Period,
+    profile::{DailyProfile, Profile, ProfileRecord},
+    scaling::ArrayScaling,
+    seasonal::{Projection, SeasonalProfile},
     solar_record::SolarRecord,
-    solarman_record::SolarmanRecord,
+    tariff::Tariff,
 };
 
+/// How Solarman exports are turned into records.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LoadOptions {
+    /// Timezone of the Solarman timestamps.
+    timezone: Tz,
+    /// Rules whose offending readings are left out, if any.
+    exclude: Option<Rules>,
+    gaps: GapOptions,
+    /// Which export wins when several have a reading for the same time.
+    precedence: Precedence,
+    /// How power readings are turned into energy.
+    integration: Integration,
+    /// Which worksheets of Excel exports are read.
+    worksheets: Worksheets,
+}
+
+impl LoadOptions {
+    #[must_use]
+    #[inline]
+    pub fn new(
+        timezone: Tz,
+        exclude: Option<Rules>,
+        gaps: GapOptions,
+        precedence: Precedence,
+        integration: Integration,
+        worksheets: Worksheets,
+    ) -> Self {
+        Self {
+            timezone,
+            exclude,
+            gaps,
+            precedence,
+            integration,
+            worksheets,
+        }
+    }
+}
+
+impl Default for LoadOptions {
+    #[inline]
+    fn default() -> Self {
+        Self::new(
+            Tz::Europe__Dublin,
+            None,
+            GapOptions::default(),
+            Precedence::default(),
+            Integration::default(),
+            Worksheets::default(),
+        )
+    }
+}
+
 #[derive(Debug)]
 pub struct SolarData {
-    setup_cost: f64,
+    investments: Investments,
     records: Vec<SolarRecord>,
     aggregation_period: Period,
     limit: usize,
+    tariff: Tariff,
+    /// Gaps between readings found while loading.
+    gaps: Vec<Gap>,
+    gap_options: GapOptions,
+    /// Time between readings beyond the nominal interval but short of a gap,
+    /// which no record covers.
+    missing: Duration,
+    /// Duplicate readings merged while loading.
+    duplicates: Deduplication,
+    integration: Integration,
+    /// The inverter's daily production counter, in kWh, for each local date
+    /// where the exports include it.
+    daily_production: BTreeMap<NaiveDate, f64>,
+    /// Energy report rows left out as covered by finer data.
+    skipped_reports: usize,
+    /// Worksheets left out as neither format, with the reason.
+    skipped_sheets: Vec<(PathBuf, String)>,
 }
 
 macro_rules! metrics {
@@ -41,59 +126,407 @@ macro_rules! metrics {
 impl SolarData {
     #[must_use]
     pub(crate) fn new(
-        setup_cost: f64,
+        investments: Investments,
         records: Vec<SolarRecord>,
         aggregation_period: Period,
         limit: usize,
+        tariff: Tariff,
     ) -> Self {
         Self {
-            setup_cost,
+            investments,
             records,
             aggregation_period,
             limit,
+            tariff,
+            gaps: Vec::new(),
+            gap_options: GapOptions::default(),
+            missing: Duration::zero(),
+            duplicates: Deduplication::default(),
+            integration: Integration::default(),
+            daily_production: BTreeMap::new(),
+            skipped_reports: 0,
+            skipped_sheets: Vec::new(),
         }
     }
 
+    /// Restricts the data to records on or after `from` and on or before `to`,
+    /// both local dates, so that every metric covers only that range.
+    #[must_use]
+    #[inline]
+    pub fn between(mut self, from: Option<NaiveDate>, to: Option<NaiveDate>) -> Self {
+        let in_range = |date: NaiveDate| {
+            from.is_none_or(|from| date >= from) && to.is_none_or(|to| date <= to)
+        };
+
+        self.records
+            .retain(|record| in_range(record.date_time().date_naive()));
+        self.gaps.retain(|gap| in_range(gap.start().date_naive()));
+        self.daily_production.retain(|date, _| in_range(*date));
+
+        self
+    }
+
     #[must_use]
     pub(crate) fn aggregate(&self, period: Period) -> Vec<AggregateSolarRecord> {
+        self.aggregate_with(period, &self.tariff)
+    }
+
+    #[must_use]
+    pub(crate) fn aggregate_with(
+        &self,
+        period: Period,
+        tariff: &Tariff,
+    ) -> Vec<AggregateSolarRecord> {
+        let charges = self.period_charges(period, tariff);
         let groups = self.records.iter().group_by(|r| period.key(&r.date_time()));
 
         let labelled_groups = groups.into_iter().map(|(date, records)| {
-            AggregateSolarRecord::new(&records.copied().collect::<Vec<_>>(), &date)
+            let charges = charges.get(&date).copied().unwrap_or_default();
+
+            AggregateSolarRecord::new(&records.copied().collect::<Vec<_>>(), &date, tariff)
+                .with_period_charges(charges)
         });
 
         labelled_groups.collect::<Vec<_>>()
     }
 
+    /// Returns the charges billed once per billing period, such as the PSO
+    /// levy and bill credits, keyed by the `period` the bill falls in: that
+    /// of the last record of each billing period with records.
+    ///
+    /// Billing periods follow `period` if it is a billing cycle, and
+    /// `tariff`'s own cycle otherwise.
+    #[must_use]
+    fn period_charges(&self, period: Period, tariff: &Tariff) -> HashMap<String, f64> {
+        let cycle = match period {
+            Period::BillingCycle(cycle) => cycle,
+            _ => tariff.billing_cycle(),
+        };
+
+        let mut charges = HashMap::<String, f64>::new();
+        let bills = self
+            .records
+            .iter()
+            .group_by(|r| cycle.bounds(r.date_time().date_naive()));
+
+        for (bounds, records) in &bills {
+            let (Some((start, end)), Some(last)) = (bounds, records.last()) else {
+                continue;
+            };
+
+            *charges.entry(period.key(&last.date_time())).or_default() +=
+                tariff.period_charges(start, end, cycle.months);
+        }
+
+        charges
+    }
+
+    /// Builds the average day of the records, bucketed by `profile` rather
+    /// than by date.
+    #[must_use]
+    #[inline]
+    pub fn profile(&self, profile: Profile) -> DailyProfile {
+        let buckets = self
+            .records
+            .iter()
+            .copied()
+            .into_group_map_by(|r| profile.bucket(&r.date_time()));
+
+        let records = buckets
+            .into_iter()
+            .sorted_by_key(|((index, _), _)| *index)
+            .map(|((_, label), records)| ProfileRecord::new(&records, &label))
+            .collect();
+
+        DailyProfile::new(profile, records)
+    }
+
+    /// Replays the records against the current tariff and each of the
+    /// `candidates`, ranking them by total cost.
+    #[must_use]
+    #[inline]
+    pub fn compare(&self, candidates: &[Tariff]) -> Comparison {
+        let period = self.aggregation_period;
+
+        let costs = core::iter::once(&self.tariff)
+            .chain(candidates)
+            .map(|tariff| {
+                (
+                    tariff.name().to_owned(),
+                    self.aggregate_with(period, tariff),
+                )
+            })
+            .collect::<Vec<_>>();
+
+        Comparison::new(costs, self.limit)
+    }
+
+    /// Replays the records without any battery and with `battery`, bought
+    /// today for `cost`, dispatched by `dispatch`.
+    #[must_use]
+    #[inline]
+    pub fn simulate(&self, battery: Battery, dispatch: Dispatch, cost: f64) -> BatterySimulation {
+        let investment = Investment::new("Battery", Some(Utc::now().date_naive()), cost, 0_f64);
+        let simulated = self.with_records(
+            battery.simulate(&self.records, &self.tariff, dispatch),
+            self.investments.with(investment),
+        );
+
+        BatterySimulation::new(battery, dispatch, cost, self.without_battery(), simulated)
+    }
+
+    /// Replays the records with `battery` under each of the `strategies`,
+    /// ranking them and the records without a battery by total cost.
+    #[must_use]
+    #[inline]
+    pub fn compare_dispatch(&self, battery: Battery, strategies: &[Dispatch]) -> Comparison {
+        let period = self.aggregation_period;
+
+        let costs = core::iter::once((
+            "No Battery".to_owned(),
+            self.without_battery().aggregate(period),
+        ))
+        .chain(strategies.iter().map(|dispatch| {
+            let records = battery.simulate(&self.records, &self.tariff, *dispatch);
+            (
+                dispatch.name().to_owned(),
+                self.with_records(records, self.investments.clone())
+                    .aggregate(period),
+            )
+        }))
+        .collect::<Vec<_>>();
+
+        Comparison::new(costs, self.limit).labelled("Strategy")
+    }
+
+    #[must_use]
+    fn without_battery(&self) -> Self {
+        self.with_records(
+            self.records.iter().map(|r| r.with_battery(0, 0)).collect(),
+            self.investments.clone(),
+        )
+    }
+
+    #[must_use]
+    fn with_records(&self, records: Vec<SolarRecord>, investments: Investments) -> Self {
+        Self {
+            gaps: self.gaps.clone(),
+            gap_options: self.gap_options,
+            missing: self.missing,
+            duplicates: self.duplicates.clone(),
+            integration: self.integration,
+            daily_production: self.daily_production.clone(),
+            skipped_reports: self.skipped_reports,
+            skipped_sheets: self.skipped_sheets.clone(),
+            ..Self::new(
+                investments,
+                records,
+                self.aggregation_period,
+                self.limit,
+                self.tariff.clone(),
+            )
+        }
+    }
+
+    /// Replays the records with production scaled by `factor`, as if the PV
+    /// array were that much larger, with the extra panels bought today for
+    /// `cost`.
+    #[must_use]
+    #[inline]
+    pub fn scale(&self, factor: f64, cost: f64) -> ArrayScaling {
+        let investment =
+            Investment::new("Extra Panels", Some(Utc::now().date_naive()), cost, 0_f64);
+
+        let baseline = self.with_records(
+            self.records.iter().map(|r| r.scaled(1_f64)).collect(),
+            self.investments.clone(),
+        );
+        let scaled = self.with_records(
+            self.records.iter().map(|r| r.scaled(factor)).collect(),
+            self.investments.with(investment),
+        );
+
+        ArrayScaling::new(factor, cost, baseline, scaled)
+    }
+
     metrics! {
-        old_cost,
-        cost,
         savings,
-        production,
-        consumption,
-        purchased,
         feed_in
     }
 
+    /// Returns the number of distinct local days in the records.
+    #[must_use]
+    pub(crate) fn days(&self) -> usize {
+        self.aggregate(Period::Day).len()
+    }
+
+    /// Returns the mean savings per `period`, or zero if there are no
+    /// records.
     #[must_use]
     pub(crate) fn mean_savings(&self, period: Period) -> f64 {
-        self.savings() / self.aggregate(period).len() as f64
+        let periods = self.aggregate(period).len();
+
+        if periods == 0 {
+            return 0_f64;
+        }
+
+        self.savings() / periods as f64
     }
 
+    /// Returns the net investment not yet covered by savings.
     #[must_use]
     pub(crate) fn remaining_setup_cost(&self) -> f64 {
-        self.setup_cost - self.savings()
+        self.investments.total() - self.savings()
+    }
+
+    /// Returns the savings of each local day in the records, oldest first.
+    #[must_use]
+    fn daily_savings(&self) -> Vec<(NaiveDate, f64)> {
+        self.records
+            .iter()
+            .group_by(|r| r.date_time().date_naive())
+            .into_iter()
+            .map(|(date, records)| {
+                let records = records.copied().collect::<Vec<_>>();
+                let key = date.to_string();
+
+                (
+                    date,
+                    AggregateSolarRecord::new(&records, &key, &self.tariff).savings(),
+                )
+            })
+            .collect()
+    }
+
+    /// Learns the mean daily savings of each calendar month from the records.
+    #[must_use]
+    #[inline]
+    pub fn seasonal_profile(&self) -> SeasonalProfile {
+        SeasonalProfile::new(&self.daily_savings())
+    }
+
+    /// Returns when each investment was, or is expected to be, paid back,
+    /// counting savings against investments in the order they were made.
+    ///
+    /// An investment is paid back on the first day, from when it was made,
+    /// that the savings to date cover it and those before it. Savings beyond
+    /// what was owed at the time still count towards later additions.
+    #[must_use]
+    #[inline]
+    pub fn paybacks(&self) -> Vec<Payback> {
+        let today = Utc::now().date_naive();
+        let profile = self.seasonal_profile();
+
+        let cumulative = self
+            .investments
+            .iter()
+            .scan(0_f64, |total, investment| {
+                *total += investment.net();
+                Some(*total)
+            })
+            .collect::<Vec<_>>();
+        let made_by = |investment: &Investment, day: NaiveDate| {
+            investment.date().is_none_or(|date| date <= day)
+        };
+
+        let mut saved = 0_f64;
+        let mut paid_back = vec![None; cumulative.len()];
+
+        for (day, savings) in self.daily_savings() {
+            saved = (saved + savings).max(0_f64);
+
+            for ((investment, total), paid_back) in
+                self.investments.iter().zip(&cumulative).zip(&mut paid_back)
+            {
+                if paid_back.is_none() && made_by(investment, day) && saved >= *total {
+                    *paid_back = Some(day);
+                }
+            }
+        }
+
+        self.investments
+            .iter()
+            .zip(cumulative)
+            .zip(paid_back)
+            .map(|((investment, cumulative), paid_back)| {
+                let paid_back = paid_back.or_else(|| {
+                    let from = investment.date().map_or(today, |date| date.max(today));
+
+                    profile
+                        .remaining_days(from, cumulative - saved)
+                        .map(|days| from + Duration::days(days))
+                });
+
+                Payback::new(investment, cumulative, paid_back)
+            })
+            .collect()
+    }
+
+    /// Projects the cumulative savings month by month from today, following
+    /// the seasonal profile, until they cover the setup cost.
+    #[must_use]
+    #[inline]
+    pub fn projection(&self) -> Projection {
+        self.seasonal_profile().project(
+            Utc::now().date_naive(),
+            self.savings(),
+            self.investments.total(),
+        )
+    }
+
+    /// Returns the number of days from today until the seasonal profile
+    /// covers the remaining setup cost, or `None` if it never does.
+    #[must_use]
+    pub(crate) fn remaining_days(&self) -> Option<i64> {
+        self.seasonal_profile()
+            .remaining_days(Utc::now().date_naive(), self.remaining_setup_cost())
+    }
+
+    #[must_use]
+    pub(crate) fn payoff_date(&self) -> Option<NaiveDate> {
+        self.remaining_days()
+            .map(|days| (Utc::now() + Duration::days(days)).date_naive())
+    }
+
+    /// Projects the mean daily savings over the lifetime of the system under
+    /// `assumptions`, counting from the first record.
+    #[must_use]
+    #[inline]
+    pub fn finance(&self, assumptions: Assumptions) -> FinancialAnalysis {
+        let start = self.records.first().map_or_else(
+            || Utc::now().date_naive(),
+            |record| record.date_time().date_naive(),
+        );
+
+        FinancialAnalysis::new(
+            assumptions,
+            &self.investments,
+            self.mean_savings(Period::Day) * 365_f64,
+            start,
+        )
+    }
+
+    /// Returns the duplicate readings merged while loading.
+    #[must_use]
+    #[inline]
+    pub fn duplicates(&self) -> &Deduplication {
+        &self.duplicates
     }
 
+    /// Returns the worksheets left out while loading as neither format, with
+    /// the reason.
     #[must_use]
-    #[allow(clippy::cast_possible_truncation)]
-    pub(crate) fn remaining_days(&self) -> i64 {
-        (self.remaining_setup_cost() / self.mean_savings(Period::Day)).round() as i64
+    #[inline]
+    pub fn skipped_sheets(&self) -> &[(PathBuf, String)] {
+        &self.skipped_sheets
     }
 
+    /// Compares the daily production integrated from the readings against
+    /// the inverter's own daily counter, where the exports include it.
     #[must_use]
-    pub(crate) fn payoff_date(&self) -> NaiveDate {
-        (Utc::now() + chrono::Duration::days(self.remaining_days())).date_naive()
+    #[inline]
+    pub fn yield_comparison(&self) -> YieldComparison {
+        YieldComparison::new(self.integration, &self.records, &self.daily_production)
     }
 
     #[inline]
@@ -101,33 +534,122 @@ impl SolarData {
         csv::write(path, &self.aggregate(self.aggregation_period))
     }
 
+    /// Loads every Solarman export in the folder at `path` as described by
+    /// `options`. Daily and monthly energy reports fill in the dates the power
+    /// exports do not cover.
+    ///
     /// # Errors
     /// # Panics
     #[inline]
     pub fn from_folder<P: AsRef<Path>>(
         path: P,
         aggregation_period: Period,
-        setup_cost: f64,
+        investments: Investments,
         limit: usize,
+        tariff: Tariff,
+        options: LoadOptions,
     ) -> anyhow::Result<Self> {
-        let raw_records = parse_spreadsheets_from_folder::<SolarmanRecord, _>(path)?;
-        let sorted_raw_records = raw_records
-            .iter()
-            .sorted_by_key(|solarman_record| solarman_record.time)
-            .collect::<Vec<_>>();
+        let LoadOptions {
+            timezone,
+            exclude,
+            gaps: gap_options,
+            precedence,
+            integration,
+            worksheets,
+        } = options;
+
+        let Exports {
+            power: files,
+            energy: reports,
+            skipped: skipped_sheets,
+        } = Exports::from_folder(path, &worksheets)?;
+
+        let mut excluded = HashSet::new();
+        let mut timed_raw_records = Vec::new();
 
-        let mut start_time: Option<DateTime<Utc>> = None;
+        for (file, (_, raw_records)) in files.iter().enumerate() {
+            let offending = exclude.map_or_else(HashSet::new, |rules| {
+                rules
+                    .check(raw_records)
+                    .into_iter()
+                    .map(|(index, _)| index)
+                    .collect()
+            });
 
-        let records = sorted_raw_records
+            let mut previous: Option<DateTime<Tz>> = None;
+
+            for (index, raw_record) in raw_records.iter().enumerate() {
+                let time = raw_record.local_time(timezone, previous);
+                previous = Some(time);
+
+                // Offending readings are kept until the intervals around them
+                // are known, so their time is left out rather than handed to
+                // a neighbour.
+                if offending.contains(&index) {
+                    excluded.insert(time);
+                }
+
+                timed_raw_records.push((time, file, raw_record));
+            }
+        }
+
+        let (timed_raw_records, duplicates) = deduplicate(timed_raw_records, &files, precedence);
+        let (mut records, gaps, missing) =
+            gap_options.records(&timed_raw_records, &excluded, integration);
+
+        // Only dates with readings of their own count as covered, not those
+        // that gap filling made up.
+        let read = timed_raw_records
             .iter()
-            .map(|raw_record| {
-                let record = SolarRecord::from_solarman_record(raw_record, start_time);
-                start_time = Some(record.date_time());
-                record
-            })
-            .collect::<Vec<_>>();
+            .filter(|(time, _)| !excluded.contains(time))
+            .map(|(time, _)| time.date_naive())
+            .collect::<HashSet<_>>();
+        let covered = daily_totals(
+            records
+                .iter()
+                .filter(|record| read.contains(&record.start().date_naive())),
+        );
+        let (report_records, skipped_reports) =
+            report_records(reports.iter().flat_map(|(_, rows)| rows), covered, timezone);
+
+        if !report_records.is_empty() {
+            // The reports know better than gap filling what happened on the
+            // dates they cover.
+            let reported = report_records
+                .iter()
+                .map(|record| record.start().date_naive())
+                .collect::<HashSet<_>>();
+            records.retain(|record| !reported.contains(&record.start().date_naive()));
+
+            records.extend(report_records);
+            records.sort_by_key(SolarRecord::date_time);
+        }
 
-        Ok(Self::new(setup_cost, records, aggregation_period, limit))
+        // The counter climbs through the day, so its largest reading is the
+        // day's total.
+        let mut daily_production = BTreeMap::<NaiveDate, f64>::new();
+        for (time, raw_record) in &timed_raw_records {
+            if excluded.contains(time) {
+                continue;
+            }
+
+            if let Some(counter) = raw_record.daily_production {
+                let total = daily_production.entry(time.date_naive()).or_default();
+                *total = total.max(counter);
+            }
+        }
+
+        Ok(Self {
+            gaps,
+            gap_options,
+            missing,
+            duplicates,
+            integration,
+            daily_production,
+            skipped_reports,
+            skipped_sheets,
+            ..Self::new(investments, records, aggregation_period, limit, tariff)
+        })
     }
 }
 
@@ -138,46 +660,223 @@ impl Display for SolarData {
 
         let aggregate_records = self.aggregate(period);
 
-        let mut total_builder = Builder::from_iter([[
-            "Total".to_owned(),
-            euro_to_string(&self.old_cost()),
-            euro_to_string(&self.cost()),
-            euro_to_string(&self.savings()),
-            watt_hour_to_string(&self.production()),
-            watt_hour_to_string(&self.consumption()),
-            watt_hour_to_string(&self.purchased()),
-            watt_hour_to_string(&self.feed_in()),
-        ]]);
-
-        total_builder.remove_header();
-        let total = total_builder.build();
-
-        let mut mean_builder = Builder::from_iter([[
-            "Mean".to_owned(),
-            euro_to_string(&(self.old_cost() / self.aggregate(period).len() as f64)),
-            euro_to_string(&(self.cost() / self.aggregate(period).len() as f64)),
-            euro_to_string(&(self.savings() / self.aggregate(period).len() as f64)),
-            watt_hour_to_string(&(self.production() / self.aggregate(period).len() as f64)),
-            watt_hour_to_string(&(self.consumption() / self.aggregate(period).len() as f64)),
-            watt_hour_to_string(&(self.purchased() / self.aggregate(period).len() as f64)),
-            watt_hour_to_string(&(self.feed_in() / self.aggregate(period).len() as f64)),
-        ]]);
+        let mean = AggregateSolarRecord::mean(&aggregate_records, "Mean");
+        let total = AggregateSolarRecord::new(&self.records, "Total", &self.tariff)
+            .with_period_charges(self.period_charges(period, &self.tariff).values().sum());
 
+        let mut mean_builder = Builder::from_iter([mean.fields()]);
         mean_builder.remove_header();
         let mean = mean_builder.build();
 
+        let mut total_builder = Builder::from_iter([total.fields()]);
+        total_builder.remove_header();
+        let total = total_builder.build();
+
         let mut table = Table::new(aggregate_records.iter().rev().take(self.limit).rev());
         table.with(Style::rounded());
 
         table.with(Concat::vertical(mean));
         table.with(Concat::vertical(total));
 
+        let payoff_date = self
+            .payoff_date()
+            .map_or_else(|| "never".to_owned(), |date| date.to_string());
+
         let output = format!(
-            "{table}\nRemaining Balance: €{:.2}\nExpected Payoff Date: {:.2}\n",
+            "{table}\nRemaining Balance: €{:.2}\nExpected Payoff Date: {payoff_date}\n",
             self.remaining_setup_cost(),
-            self.payoff_date()
         );
 
-        write!(f, "{output}")
+        write!(f, "{output}")?;
+
+        let conflicts = self.duplicates.conflicts();
+        if !conflicts.is_empty() {
+            let shown = conflicts.len().saturating_sub(self.limit);
+            let mut conflicts = Table::new(conflicts.iter().skip(shown));
+            conflicts.with(Style::rounded());
+            writeln!(f, "Conflicting Duplicates:\n{conflicts}")?;
+        }
+
+        if self.skipped_reports > 0 {
+            writeln!(
+                f,
+                "Reports: {} daily or monthly rows skipped as covered by finer data",
+                self.skipped_reports,
+            )?;
+        }
+
+        if self.missing > Duration::zero() {
+            writeln!(
+                f,
+                "Short Gaps: {:.1} hours between readings more than {} minutes apart, not counted",
+                self.missing.num_minutes() as f64 / 60_f64,
+                self.gap_options.interval().num_minutes(),
+            )?;
+        }
+
+        if !self.gaps.is_empty() {
+            let missing = self
+                .gaps
+                .iter()
+                .map(|gap| gap.length().num_minutes())
+                .sum::<i64>();
+
+            writeln!(
+                f,
+                "Gaps: {} longer than {} minutes, {:.1} hours in total ({})",
+                self.gaps.len(),
+                self.gap_options.threshold().num_minutes(),
+                missing as f64 / 60_f64,
+                self.gap_options.fill(),
+            )?;
+
+            let mut gaps = Table::new(self.gaps.iter().rev().take(self.limit).rev());
+            gaps.with(Style::rounded());
+            writeln!(f, "{gaps}")?;
+        }
+
+        if self.investments.len() > 1 {
+            let mut paybacks = Table::new(self.paybacks());
+            paybacks.with(Style::rounded());
+            writeln!(f, "{paybacks}")?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::{ensure, Context};
+
+    #[test]
+    fn test_between() -> anyhow::Result<()> {
+        let records = SolarRecord::hourly("2024-06-01 00:30", 72, |_| (1000, 0, 1000, 0, 0))?;
+
+        let data = SolarData::new(
+            Investments::default(),
+            records,
+            Period::Day,
+            12,
+            Tariff::default(),
+        );
+        let day = NaiveDate::from_ymd_opt(2024, 6, 2).context("Invalid date")?;
+        let production = |data: &SolarData| {
+            data.records
+                .iter()
+                .map(SolarRecord::production)
+                .sum::<f64>()
+        };
+
+        let data = data.between(Some(day), None);
+        ensure!((production(&data) - 48_000_f64).abs() < f64::EPSILON);
+
+        let data = data.between(None, Some(day));
+        ensure!((production(&data) - 24_000_f64).abs() < f64::EPSILON);
+        ensure!(data.aggregate(Period::Day).len() == 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_period_charges() -> anyhow::Result<()> {
+        let records = SolarRecord::hourly("2024-12-30 00:30", 96, |_| (0, 1000, -1000, 0, 0))?;
+
+        let data = SolarData::new(
+            Investments::default(),
+            records,
+            Period::Day,
+            12,
+            Tariff::default(),
+        );
+
+        // The November to December and January to February bills each carry
+        // two months of levy, on the last day of each with records.
+        let days = data.aggregate(Period::Day);
+        let costs = days
+            .iter()
+            .map(AggregateSolarRecord::cost)
+            .collect::<Vec<_>>();
+        let levy = 2_f64 * 1.73;
+
+        ensure!(costs.len() == 4);
+        ensure!((costs[1] - costs[0] - levy).abs() < 1e-9);
+        ensure!((costs[3] - costs[2] - levy).abs() < 1e-9);
+        ensure!(days.iter().all(|day| day.savings().abs() < 1e-9));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_mean_savings_without_records() -> anyhow::Result<()> {
+        let data = SolarData::new(
+            Investments::default(),
+            Vec::new(),
+            Period::Day,
+            12,
+            Tariff::default(),
+        );
+
+        ensure!(data.mean_savings(Period::Day).abs() < f64::EPSILON);
+        let assumptions = Assumptions::new(4_f64, 2_f64, 0.5, 0_f64, None, 0_f64, 25);
+        ensure!(data.finance(assumptions).npv().is_finite());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_paybacks() -> anyhow::Result<()> {
+        let records = SolarRecord::hourly("2024-06-01 00:30", 240, |_| (1000, 1000, 0, 0, 0))?;
+
+        let data = SolarData::new(
+            Investments::default(),
+            records,
+            Period::Day,
+            12,
+            Tariff::default(),
+        );
+        let per_day = data.daily_savings().first().context("No savings")?.1;
+        let june = |day| NaiveDate::from_ymd_opt(2024, 6, day).context("Invalid date");
+
+        // The panels are paid back on the third day. The surplus saved over
+        // the days after that counts towards the battery, which is paid back
+        // on the day after it was bought rather than once it has saved it.
+        let investments = Investments::lump_sum(per_day * 2.5).with(Investment::new(
+            "Battery",
+            Some(june(8)?),
+            per_day * 6.2,
+            0_f64,
+        ));
+        let paybacks = SolarData {
+            investments,
+            ..data
+        }
+        .paybacks();
+
+        ensure!(paybacks.len() == 2);
+        ensure!(paybacks[0].paid_back() == Some(june(3)?));
+        ensure!(paybacks[1].paid_back() == Some(june(9)?));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_scale() -> anyhow::Result<()> {
+        let records = SolarRecord::hourly("2024-06-01 00:30", 48, |_| (1000, 1500, -500, 0, 0))?;
+
+        let data = SolarData::new(
+            Investments::default(),
+            records,
+            Period::Day,
+            12,
+            Tariff::default(),
+        );
+        let scaling = data.scale(2_f64, 0_f64);
+
+        ensure!((scaling.feed_in_change() - 24_000_f64).abs() < f64::EPSILON);
+        ensure!(scaling.savings_change() > 0_f64);
+
+        Ok(())
     }
 }
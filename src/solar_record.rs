@@ -1,106 +1,310 @@
-use chrono::{DateTime, Duration, Utc};
+use chrono::{DateTime, Duration};
+use chrono_tz::Tz;
 
-use crate::{rate::Rate, solarman_record::SolarmanRecord};
+use crate::{rate::Rate, solarman_record::SolarmanRecord, tariff::Tariff};
 
 #[derive(Debug, Clone, Copy)]
 pub(crate) struct SolarRecord {
-    date_time: DateTime<Utc>,
+    date_time: DateTime<Tz>,
     duration: Duration,
     production: u32,
     consumption: u32,
-    grid: i32,
+    /// Mean power bought from the grid, in W.
+    import: u32,
+    /// Mean power fed into the grid, in W.
+    export: u32,
+    /// Mean power charging the battery, in W.
+    charge: u32,
+    /// Mean power drawn from the battery, in W.
+    discharge: u32,
+    soc: u8,
 }
 
 impl SolarRecord {
     #[must_use]
     pub fn new(
-        date_time: DateTime<Utc>,
+        date_time: DateTime<Tz>,
         duration: Duration,
         production: u32,
         consumption: u32,
         grid: i32,
+        battery: i32,
+        soc: u8,
     ) -> Self {
+        let (export, import) = split(grid.into());
+        let (charge, discharge) = split(battery.into());
+
         Self {
             date_time,
             duration,
             production,
             consumption,
-            grid,
+            import,
+            export,
+            charge,
+            discharge,
+            soc,
+        }
+    }
+
+    /// Returns a copy of the record with the battery replaced by one
+    /// charging at `battery` W, balancing the grid against the recorded
+    /// production and consumption.
+    #[must_use]
+    pub fn with_battery(&self, battery: i32, soc: u8) -> Self {
+        let (export, import) = split(balance(self.production, self.consumption, battery.into()));
+        let (charge, discharge) = split(battery.into());
+
+        Self {
+            import,
+            export,
+            charge,
+            discharge,
+            soc,
+            ..*self
+        }
+    }
+
+    /// Returns a copy of the record with production scaled by `factor`,
+    /// balancing the grid against the recorded consumption and battery.
+    #[must_use]
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    pub fn scaled(&self, factor: f64) -> Self {
+        let production = (f64::from(self.production) * factor)
+            .round()
+            .clamp(0_f64, f64::from(u32::MAX)) as u32;
+
+        let battery = i64::from(self.charge) - i64::from(self.discharge);
+        let (export, import) = split(balance(production, self.consumption, battery));
+
+        Self {
+            production,
+            import,
+            export,
+            ..*self
         }
     }
 
+    /// Returns the power left over after consumption, in W; negative when
+    /// consumption exceeds production.
     #[must_use]
-    fn rate(&self) -> Rate {
-        self.date_time.into()
+    pub fn surplus(&self) -> f64 {
+        f64::from(self.production) - f64::from(self.consumption)
     }
 
     #[must_use]
-    pub fn date_time(&self) -> DateTime<Utc> {
+    pub fn rate<'t>(&self, tariff: &'t Tariff) -> &'t Rate {
+        tariff.rate(self.date_time)
+    }
+
+    #[must_use]
+    pub fn date_time(&self) -> DateTime<Tz> {
         self.date_time
     }
 
     #[must_use]
-    pub fn standing_charge(&self) -> f64 {
-        self.rate().standing_charge() * (self.duration.num_minutes() as f64 / 1440_f64)
+    pub fn duration(&self) -> Duration {
+        self.duration
+    }
+
+    /// Returns when the time the record covers starts; it is stamped at the
+    /// end.
+    #[must_use]
+    pub fn start(&self) -> DateTime<Tz> {
+        self.date_time - self.duration
+    }
+
+    /// Returns the battery state of charge, as a percentage.
+    #[must_use]
+    pub fn soc(&self) -> u8 {
+        self.soc
+    }
+
+    #[must_use]
+    pub fn standing_charge(&self, tariff: &Tariff) -> f64 {
+        self.rate(tariff).standing_charge() * (self.duration.num_minutes() as f64 / 1440_f64)
+    }
+
+    /// Returns the bill for importing `energy` Wh during the record: the unit
+    /// cost and standing charge plus VAT. The PSO levy and bill credits are
+    /// charged once per billing period rather than per record.
+    #[must_use]
+    fn bill(&self, tariff: &Tariff, energy: f64) -> f64 {
+        let rate = self.rate(tariff);
+
+        rate.with_vat(rate.import_cost(energy, self.date_time) + self.standing_charge(tariff))
     }
 
     #[must_use]
-    pub fn old_cost(&self) -> f64 {
-        let consumption = i32::try_from(self.consumption).unwrap_or(i32::MAX);
-        self.rate().cost(consumption, self.date_time)
-            * (self.duration.num_minutes() as f64 / 60_f64)
-            + self.standing_charge()
+    pub fn old_cost(&self, tariff: &Tariff) -> f64 {
+        self.bill(tariff, self.consumption())
     }
 
     #[must_use]
-    pub fn cost(&self) -> f64 {
-        self.rate().cost(-self.grid, self.date_time) * (self.duration.num_minutes() as f64 / 60_f64)
-            + self.standing_charge()
+    pub fn cost(&self, tariff: &Tariff) -> f64 {
+        self.bill(tariff, self.purchased()) - self.export_credit(tariff)
+    }
+
+    #[must_use]
+    pub fn export_credit(&self, tariff: &Tariff) -> f64 {
+        self.rate(tariff)
+            .export_credit(self.feed_in(), self.date_time)
     }
 
     #[must_use]
     pub fn production(&self) -> f64 {
-        f64::from(self.production) * (self.duration.num_minutes() as f64 / 60_f64)
+        self.energy(self.production)
     }
 
     #[must_use]
     pub fn consumption(&self) -> f64 {
-        f64::from(self.consumption) * (self.duration.num_minutes() as f64 / 60_f64)
+        self.energy(self.consumption)
     }
 
     #[must_use]
     pub fn purchased(&self) -> f64 {
-        if self.grid < 0_i32 {
-            f64::from(self.grid.abs()) * (self.duration.num_minutes() as f64 / 60_f64)
-        } else {
-            0_f64
-        }
+        self.energy(self.import)
     }
 
     #[must_use]
     pub fn feed_in(&self) -> f64 {
-        if self.grid > 0_i32 {
-            f64::from(self.grid) * (self.duration.num_minutes() as f64 / 60_f64)
-        } else {
-            0_f64
-        }
+        self.energy(self.export)
+    }
+
+    #[must_use]
+    pub fn battery_charge(&self) -> f64 {
+        self.energy(self.charge)
+    }
+
+    #[must_use]
+    pub fn battery_discharge(&self) -> f64 {
+        self.energy(self.discharge)
+    }
+
+    /// Returns the energy, in Wh, of `power` W held over the record.
+    fn energy(&self, power: u32) -> f64 {
+        f64::from(power) * (self.duration.num_minutes() as f64 / 60_f64)
     }
 
     pub fn from_solarman_record(
         record: &SolarmanRecord,
-        start_time: Option<DateTime<Utc>>,
+        date_time: DateTime<Tz>,
+        duration: Duration,
     ) -> Self {
-        let duration = match start_time {
-            Some(t) => record.time - t,
-            None => Duration::minutes(5),
-        };
-
         Self::new(
-            record.time,
+            date_time,
             duration,
-            record.production,
-            record.consumption,
+            u32::try_from(record.production).unwrap_or_default(),
+            u32::try_from(record.consumption).unwrap_or_default(),
             record.grid,
+            record.battery,
+            record.soc,
         )
     }
+
+    /// Returns a record ending at `date_time`, between this record and
+    /// `next`, with readings interpolated linearly between theirs.
+    #[must_use]
+    pub fn interpolate(&self, next: &Self, date_time: DateTime<Tz>, duration: Duration) -> Self {
+        let span = (next.date_time - self.date_time).num_seconds().max(1) as f64;
+        let fraction = (date_time - self.date_time).num_seconds() as f64 / span;
+
+        self.blend(next, fraction, date_time, duration)
+    }
+
+    /// Returns a copy of the record with its power taken as the mean over
+    /// the interval since `previous`, weighting this reading by `weight` and
+    /// the previous one by the rest. Each direction of the grid and battery
+    /// flows is averaged on its own, so an interval that swings from import
+    /// to export counts some of both rather than netting them out.
+    #[must_use]
+    pub fn integrated(&self, previous: &Self, weight: f64) -> Self {
+        Self {
+            soc: self.soc,
+            ..previous.blend(self, weight, self.date_time, self.duration)
+        }
+    }
+
+    /// Returns a record ending at `date_time` with readings `fraction` of the
+    /// way from this record's to `next`'s.
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    fn blend(
+        &self,
+        next: &Self,
+        fraction: f64,
+        date_time: DateTime<Tz>,
+        duration: Duration,
+    ) -> Self {
+        let between = |a: u32, b: u32| {
+            (f64::from(a) + (f64::from(b) - f64::from(a)) * fraction).round() as u32
+        };
+
+        Self {
+            date_time,
+            duration,
+            production: between(self.production, next.production),
+            consumption: between(self.consumption, next.consumption),
+            import: between(self.import, next.import),
+            export: between(self.export, next.export),
+            charge: between(self.charge, next.charge),
+            discharge: between(self.discharge, next.discharge),
+            soc: between(self.soc.into(), next.soc.into()) as u8,
+        }
+    }
+}
+
+/// Returns the grid power, in W, left by `production` after `consumption` and
+/// charging the battery at `battery`; positive when feeding in.
+fn balance(production: u32, consumption: u32, battery: i64) -> i64 {
+    i64::from(production) - i64::from(consumption) - battery
+}
+
+/// Splits a signed power, in W, into its positive part and the magnitude of
+/// its negative part.
+#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+fn split(power: i64) -> (u32, u32) {
+    let part = |power: i64| power.clamp(0, i64::from(u32::MAX)) as u32;
+
+    (part(power), part(-power))
+}
+
+/// The readings of a test record: production, consumption, grid and battery
+/// power, in W, and state of charge.
+#[cfg(test)]
+pub(crate) type Readings = (u32, u32, i32, i32, u8);
+
+#[cfg(test)]
+impl SolarRecord {
+    /// Returns `count` records an hour long and an hour apart in Dublin, the
+    /// first stamped `start` ("YYYY-MM-DD HH:MM"), each with the readings
+    /// `readings` gives for its position.
+    pub(crate) fn hourly<F>(start: &str, count: usize, readings: F) -> anyhow::Result<Vec<Self>>
+    where
+        F: Fn(usize) -> Readings,
+    {
+        use anyhow::Context;
+        use chrono::{NaiveDateTime, TimeZone};
+        use chrono_tz::Europe::Dublin;
+
+        let start = Dublin
+            .from_local_datetime(&NaiveDateTime::parse_from_str(start, "%Y-%m-%d %H:%M")?)
+            .single()
+            .context("Failed to create DateTime<Tz> value")?;
+
+        (0..count)
+            .map(|index| {
+                let (production, consumption, grid, battery, soc) = readings(index);
+
+                Ok(Self::new(
+                    start + Duration::hours(i64::try_from(index)?),
+                    Duration::hours(1),
+                    production,
+                    consumption,
+                    grid,
+                    battery,
+                    soc,
+                ))
+            })
+            .collect()
+    }
 }
@@ -1,25 +1,28 @@
-use chrono::{DateTime, TimeZone, Utc};
+use chrono::{DateTime, Duration, LocalResult, NaiveDateTime, TimeZone};
+use chrono_tz::Tz;
 use num_traits::{Num, NumCast};
 use serde::{Deserialize, Deserializer};
 
 /// A record of solar power production and consumption
-#[derive(Debug, Deserialize, PartialEq, Eq)]
+#[derive(Debug, Deserialize, PartialEq)]
 pub(crate) struct SolarmanRecord {
-    /// The time at which the record was updated.
+    /// The local wall-clock time at which the record was updated.
     #[serde(rename = "Updated Time", deserialize_with = "deserialize_date")]
-    pub time: DateTime<Utc>,
-    /// The amount of power being produced, in watts.
+    pub time: NaiveDateTime,
+    /// The amount of power being produced, in watts. Signed so that faulty
+    /// negative readings can be reported rather than failing to parse.
     #[serde(
         rename = "Production Power(W)",
         deserialize_with = "deserialize_decimal"
     )]
-    pub production: u32,
-    /// The amount of power being consumed, in watts.
+    pub production: i32,
+    /// The amount of power being consumed, in watts. Signed for the same
+    /// reason as `production`.
     #[serde(
         rename = "Consumption Power(W)",
         deserialize_with = "deserialize_decimal"
     )]
-    pub consumption: u32,
+    pub consumption: i32,
     /// The amount of power being drawn or fed back into the grid, in watts.
     /// Positive values indicate power being fed back into the grid.
     /// Negative values indicate power being drawn from the grid.
@@ -28,11 +31,53 @@ pub(crate) struct SolarmanRecord {
     /// The amount of power being charged or discharged from the battery, in watts.
     /// Positive values indicate power being charged into the battery.
     /// Negative values indicate power being discharged from the battery.
-    #[serde(rename = "Battery Power(W)", deserialize_with = "deserialize_decimal")]
+    /// Zero when the export has no battery column.
+    #[serde(
+        rename = "Battery Power(W)",
+        default,
+        deserialize_with = "deserialize_decimal"
+    )]
     pub battery: i32,
     /// The state of charge of the battery, as a percentage.
-    #[serde(rename = "SoC(%)", deserialize_with = "deserialize_decimal")]
+    #[serde(rename = "SoC(%)", default, deserialize_with = "deserialize_decimal")]
     pub soc: u8,
+    /// The energy the inverter counts as produced so far that day, in
+    /// kilowatt-hours, when the export includes it.
+    #[serde(
+        rename = "Daily Production(kWh)",
+        default,
+        deserialize_with = "deserialize_optional_decimal"
+    )]
+    pub daily_production: Option<f64>,
+}
+
+impl SolarmanRecord {
+    /// Resolves the wall-clock update time of the record in the given
+    /// timezone.
+    ///
+    /// Records are expected in chronological order, with `previous` being the
+    /// resolved time of the record before this one in the same export. During
+    /// the repeated hour when clocks go back, the first pass is resolved to
+    /// the earlier offset and readings after the clock change, detected by
+    /// time not moving forward, to the later one. Times skipped when clocks go
+    /// forward are resolved using the offset from before the change.
+    #[must_use]
+    pub fn local_time(&self, timezone: Tz, previous: Option<DateTime<Tz>>) -> DateTime<Tz> {
+        match timezone.from_local_datetime(&self.time) {
+            LocalResult::Single(time) => time,
+            LocalResult::Ambiguous(earliest, latest) => match previous {
+                Some(previous) if previous >= earliest => latest,
+                _ => earliest,
+            },
+            LocalResult::None => {
+                let before = timezone.from_local_datetime(&(self.time - Duration::hours(1)));
+                before.earliest().map_or_else(
+                    || timezone.from_utc_datetime(&self.time),
+                    |time| time + Duration::hours(1),
+                )
+            }
+        }
+    }
 }
 
 /// Deserializes a decimal value from a string.
@@ -70,15 +115,35 @@ where
     Ok(d)
 }
 
+/// Deserializes an optional decimal value from a string, treating an empty
+/// string as no value.
+///
+/// # Errors
+///
+/// Will return an error if the string cannot be parsed as a decimal value.
+fn deserialize_optional_decimal<'de, D>(deserializer: D) -> Result<Option<f64>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let s = String::deserialize(deserializer)?;
+
+    if s.is_empty() {
+        return Ok(None);
+    }
+
+    s.parse().map(Some).map_err(serde::de::Error::custom)
+}
+
 /// Deserializes a date and time value from a string.
 ///
 /// This function is used to deserialize date and time values from strings in
-/// the `SolarmanRecord` struct.
+/// the `SolarmanRecord` struct. Solarman reports local wall-clock time, so no
+/// timezone is applied here.
 ///
 /// # Errors
 ///
 /// Will return an error if the string cannot be parsed as a date and time value.
-fn deserialize_date<'de, D>(deserializer: D) -> Result<DateTime<Utc>, D::Error>
+fn deserialize_date<'de, D>(deserializer: D) -> Result<NaiveDateTime, D::Error>
 where
     D: Deserializer<'de>,
 {
@@ -88,7 +153,7 @@ where
 
     let datetime = formats
         .iter()
-        .find_map(|f| Utc.datetime_from_str(&s, f).ok());
+        .find_map(|f| NaiveDateTime::parse_from_str(&s, f).ok());
 
     datetime.ok_or(serde::de::Error::custom("Failed to parse date and time"))
 }
@@ -97,6 +162,8 @@ where
 mod tests {
     use super::*;
     use anyhow::{ensure, Context};
+    use chrono::{NaiveDate, Utc};
+    use chrono_tz::Europe::Dublin;
     use serde::de::{
         value::{self, F64Deserializer, StrDeserializer},
         IntoDeserializer,
@@ -119,14 +186,13 @@ mod tests {
     #[test]
     fn test_deserialize_date() -> anyhow::Result<()> {
         let input = "2023/05/24 01:35";
-        let expected = Utc
-            .with_ymd_and_hms(2023, 5, 24, 1, 35, 0)
-            .single()
-            .context("Failed to create expected DateTime<Utc> value")?;
+        let expected = NaiveDate::from_ymd_opt(2023, 5, 24)
+            .and_then(|date| date.and_hms_opt(1, 35, 0))
+            .context("Failed to create expected NaiveDateTime value")?;
 
         let deserializer: StrDeserializer<value::Error> = input.into_deserializer();
 
-        let result: DateTime<Utc> = deserialize_date(deserializer)?;
+        let result: NaiveDateTime = deserialize_date(deserializer)?;
         ensure!(result == expected);
 
         Ok(())
@@ -154,10 +220,9 @@ mod tests {
             Token::StructEnd,
         ];
 
-        let time = Utc
-            .with_ymd_and_hms(2023, 5, 24, 1, 35, 0)
-            .single()
-            .context("Failed to create expected DateTime<Utc> value")?;
+        let time = NaiveDate::from_ymd_opt(2023, 5, 24)
+            .and_then(|date| date.and_hms_opt(1, 35, 0))
+            .context("Failed to create expected NaiveDateTime value")?;
 
         let expected = SolarmanRecord {
             time,
@@ -166,10 +231,74 @@ mod tests {
             grid: -789,
             battery: 1011,
             soc: 12,
+            daily_production: None,
         };
 
         assert_de_tokens(&expected, &input);
 
         Ok(())
     }
+
+    fn record_at(time: &str) -> anyhow::Result<SolarmanRecord> {
+        Ok(SolarmanRecord {
+            time: NaiveDateTime::parse_from_str(time, "%Y/%m/%d %H:%M")?,
+            production: 0,
+            consumption: 0,
+            grid: 0,
+            battery: 0,
+            soc: 0,
+            daily_production: None,
+        })
+    }
+
+    #[test]
+    fn test_local_time_summer() -> anyhow::Result<()> {
+        let time = record_at("2024/06/01 12:00")?.local_time(Dublin, None);
+        let expected = Utc
+            .with_ymd_and_hms(2024, 6, 1, 11, 0, 0)
+            .single()
+            .context("Failed to create expected DateTime<Utc> value")?;
+
+        ensure!(time == expected);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_local_time_repeated_hour() -> anyhow::Result<()> {
+        let times = [
+            "2024/10/27 00:55",
+            "2024/10/27 01:00",
+            "2024/10/27 01:55",
+            "2024/10/27 01:00",
+            "2024/10/27 01:55",
+            "2024/10/27 02:00",
+        ];
+
+        let mut previous = None;
+        let mut resolved = Vec::new();
+
+        for time in times {
+            let time = record_at(time)?.local_time(Dublin, previous);
+            previous = Some(time);
+            resolved.push(time.with_timezone(&Utc).format("%H:%M").to_string());
+        }
+
+        ensure!(resolved == ["23:55", "00:00", "00:55", "01:00", "01:55", "02:00"]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_local_time_skipped_hour() -> anyhow::Result<()> {
+        let time = record_at("2024/03/31 01:30")?.local_time(Dublin, None);
+        let expected = Utc
+            .with_ymd_and_hms(2024, 3, 31, 1, 30, 0)
+            .single()
+            .context("Failed to create expected DateTime<Utc> value")?;
+
+        ensure!(time == expected);
+
+        Ok(())
+    }
 }
@@ -0,0 +1,569 @@
+use std::{ffi::OsStr, fs, path::Path};
+
+use anyhow::{anyhow, bail, ensure, Context};
+use chrono::{DateTime, NaiveDate, Weekday};
+use chrono_tz::Tz;
+use serde::Deserialize;
+
+use crate::{
+    period::BillingCycle,
+    rate::{Rate, Slot},
+};
+
+/// The tariff history used when no tariff file is given.
+const DEFAULT_TARIFF: &str = include_str!("../tariffs/default.toml");
+
+/// A one-off credit on a bill, such as a government energy credit.
+#[derive(Debug, Clone, Deserialize, PartialEq)]
+pub(crate) struct Credit {
+    /// The name of the credit, e.g. "Energy Credit 2024".
+    pub name: String,
+    /// The day on which the credit is applied.
+    pub date: NaiveDate,
+    /// The amount of the credit, in euro.
+    pub amount: f64,
+}
+
+/// A dated list of tariff versions, loaded from a TOML or JSON tariff file.
+#[derive(Debug, Clone, Deserialize, PartialEq)]
+pub struct Tariff {
+    /// The name of the tariff, defaulting to the tariff file name.
+    #[serde(default)]
+    name: Option<String>,
+    /// Public holidays, on which `holidays` bands apply.
+    #[serde(default)]
+    holidays: Vec<NaiveDate>,
+    /// One-off bill credits.
+    #[serde(default)]
+    credits: Vec<Credit>,
+    /// The billing cycle the PSO levy and credits are applied per.
+    #[serde(default)]
+    billing: BillingCycle,
+    versions: Vec<Rate>,
+}
+
+impl Tariff {
+    /// Reads a tariff from a `.toml` or `.json` file.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the file cannot be read, has an unsupported
+    /// extension or does not describe a valid tariff.
+    #[inline]
+    pub fn from_file<P: AsRef<Path>>(path: P) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let contents = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read tariff file {}", path.display()))?;
+
+        let mut tariff = match path.extension().and_then(OsStr::to_str) {
+            Some("toml") => Self::from_toml(&contents),
+            Some("json") => Self::from_json(&contents),
+            _ => Err(anyhow!(
+                "Unsupported tariff file extension: {}",
+                path.display()
+            )),
+        }
+        .with_context(|| format!("Invalid tariff file {}", path.display()))?;
+
+        if tariff.name.is_none() {
+            tariff.name = path
+                .file_stem()
+                .map(|stem| stem.to_string_lossy().into_owned());
+        }
+
+        Ok(tariff)
+    }
+
+    /// Parses a tariff from a TOML string.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the string does not describe a valid tariff.
+    #[inline]
+    pub fn from_toml(s: &str) -> anyhow::Result<Self> {
+        toml::from_str::<Self>(s)?.validate()
+    }
+
+    /// Parses a tariff from a JSON string.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the string does not describe a valid tariff.
+    #[inline]
+    pub fn from_json(s: &str) -> anyhow::Result<Self> {
+        serde_json::from_str::<Self>(s)?.validate()
+    }
+
+    #[must_use]
+    #[inline]
+    pub fn name(&self) -> &str {
+        self.name.as_deref().unwrap_or("Tariff")
+    }
+
+    /// Sorts the versions by start date, shares the holiday list with them
+    /// and checks that every version prices every hour of every day.
+    fn validate(mut self) -> anyhow::Result<Self> {
+        ensure!(!self.versions.is_empty(), "Tariff has no versions");
+        ensure!(
+            self.billing.months > 0,
+            "Billing cycle must be at least one month long"
+        );
+
+        self.versions.sort_by_key(|version| version.start);
+
+        for version in &mut self.versions {
+            version.holidays.clone_from(&self.holidays);
+        }
+
+        let weekdays = [
+            Weekday::Mon,
+            Weekday::Tue,
+            Weekday::Wed,
+            Weekday::Thu,
+            Weekday::Fri,
+            Weekday::Sat,
+            Weekday::Sun,
+        ];
+
+        for version in &self.versions {
+            for band in version.all_bands() {
+                band.validate()
+                    .with_context(|| format!("Invalid band in {}", version.name))?;
+            }
+
+            for (weekday, month, holiday, hour) in
+                itertools::iproduct!(weekdays, 1..=12, [false, true], 0..24)
+            {
+                let slot = Slot {
+                    hour,
+                    weekday,
+                    month,
+                    holiday,
+                };
+
+                let missing = if version.band(slot).is_none() {
+                    "unit rate"
+                } else if version.export_rate(slot).is_none() {
+                    "export rate"
+                } else {
+                    continue;
+                };
+
+                bail!(
+                    "{} has no {missing} band covering {hour:02}:00 on {weekday}{} in month {month}",
+                    version.name,
+                    if holiday { " holidays" } else { "" }
+                );
+            }
+        }
+
+        Ok(self)
+    }
+
+    /// Returns the billing cycle the PSO levy and credits are applied per.
+    #[must_use]
+    pub(crate) fn billing_cycle(&self) -> BillingCycle {
+        self.billing
+    }
+
+    /// Returns the charges billed once for the billing period from `start` to
+    /// `end`, both local dates, `months` months long: the PSO levy of the
+    /// version in effect at its start, plus VAT, less the credits applied
+    /// within it.
+    #[must_use]
+    pub(crate) fn period_charges(&self, start: NaiveDate, end: NaiveDate, months: u32) -> f64 {
+        let rate = self.version(start);
+
+        rate.with_vat(rate.pso_levy() * f64::from(months)) - self.credits(start, end)
+    }
+
+    /// Returns the total of the credits applied from `start` to `end`, both
+    /// local dates, inclusive.
+    #[must_use]
+    fn credits(&self, start: NaiveDate, end: NaiveDate) -> f64 {
+        self.credits
+            .iter()
+            .filter(|credit| start <= credit.date && credit.date <= end)
+            .map(|credit| credit.amount)
+            .sum()
+    }
+
+    /// Returns the version in effect on the local date of `date`, falling back
+    /// to the earliest version for dates before it.
+    #[must_use]
+    pub(crate) fn rate(&self, date: DateTime<Tz>) -> &Rate {
+        self.version(date.date_naive())
+    }
+
+    /// Returns the version in effect on `day`, falling back to the earliest
+    /// version for days before it.
+    fn version(&self, day: NaiveDate) -> &Rate {
+        self.versions
+            .iter()
+            .rev()
+            .find(|version| version.start <= day)
+            .unwrap_or(&self.versions[0])
+    }
+}
+
+impl Default for Tariff {
+    #[inline]
+    #[allow(clippy::expect_used)]
+    fn default() -> Self {
+        Self::from_toml(DEFAULT_TARIFF).expect("Built-in tariff file is invalid")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::{ensure, Context};
+    use chrono::{Duration, TimeZone};
+    use chrono_tz::Europe::Dublin;
+
+    use crate::solar_record::SolarRecord;
+
+    #[test]
+    fn test_default_tariff() -> anyhow::Result<()> {
+        let tariff = Tariff::from_toml(DEFAULT_TARIFF)?;
+
+        let date = Dublin
+            .with_ymd_and_hms(2024, 6, 1, 12, 0, 0)
+            .single()
+            .context("Failed to create DateTime<Tz> value")?;
+        ensure!(tariff.rate(date).name == "Electric Ireland V1");
+
+        let date = Dublin
+            .with_ymd_and_hms(2025, 1, 15, 0, 0, 0)
+            .single()
+            .context("Failed to create DateTime<Tz> value")?;
+        ensure!(tariff.rate(date).name == "Energia V0");
+
+        // The levy was a credit in 2022/23 and changes every October.
+        let levy = |year, month| -> anyhow::Result<f64> {
+            let date = Dublin
+                .with_ymd_and_hms(year, month, 1, 0, 0, 0)
+                .single()
+                .context("Failed to create DateTime<Tz> value")?;
+
+            Ok(tariff.rate(date).pso_levy())
+        };
+        ensure!(levy(2023, 6)? < 0_f64);
+        ensure!(levy(2024, 9)?.abs() < f64::EPSILON);
+        ensure!(levy(2024, 10)? > 0_f64);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_local_time_bands() -> anyhow::Result<()> {
+        let tariff = Tariff::default();
+
+        // 08:30 IST is 07:30 UTC, which would fall in the night band
+        let date = Dublin
+            .with_ymd_and_hms(2024, 6, 1, 8, 30, 0)
+            .single()
+            .context("Failed to create DateTime<Tz> value")?;
+        let rate = tariff.rate(date);
+        let band = rate.band(rate.slot(date));
+
+        ensure!(band.map(|band| band.name.as_str()) == Some("Day"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_from_json() -> anyhow::Result<()> {
+        let input = r#"{
+            "versions": [{
+                "name": "Flat",
+                "start": "2024-01-01",
+                "standing_charge": 0.5,
+                "export": 0.2,
+                "bands": [{ "name": "All Day", "hours": [
+                    0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11,
+                    12, 13, 14, 15, 16, 17, 18, 19, 20, 21, 22, 23
+                ], "rate": 0.3 }]
+            }]
+        }"#;
+
+        let tariff = Tariff::from_json(input)?;
+        let date = Dublin
+            .with_ymd_and_hms(2023, 6, 1, 12, 0, 0)
+            .single()
+            .context("Failed to create DateTime<Tz> value")?;
+
+        ensure!(tariff.rate(date).name == "Flat");
+        ensure!((tariff.rate(date).import_cost(1000_f64, date) - 0.3).abs() < f64::EPSILON);
+        ensure!((tariff.rate(date).export_credit(1000_f64, date) - 0.2).abs() < f64::EPSILON);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_day_and_season_bands() -> anyhow::Result<()> {
+        let input = r#"
+            holidays = ["2024-12-26"]
+
+            [[versions]]
+            name = "Weekend Saver"
+            start = "2024-01-01"
+            standing_charge = 0.5
+            export = 0.2
+
+            [[versions.bands]]
+            name = "Free Weekend"
+            days = ["weekends", "holidays"]
+            hours = [9, 10, 11, 12, 13, 14, 15, 16]
+            rate = 0.0
+
+            [[versions.bands]]
+            name = "Winter Peak"
+            seasons = ["winter"]
+            hours = [17, 18]
+            rate = 0.5
+
+            [[versions.bands]]
+            name = "Standard"
+            hours = [
+                0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11,
+                12, 13, 14, 15, 16, 17, 18, 19, 20, 21, 22, 23
+            ]
+            rate = 0.3
+        "#;
+
+        let tariff = Tariff::from_toml(input)?;
+        let band = |(year, month, day, hour)| -> anyhow::Result<String> {
+            let date = Dublin
+                .with_ymd_and_hms(year, month, day, hour, 0, 0)
+                .single()
+                .context("Failed to create DateTime<Tz> value")?;
+
+            let rate = tariff.rate(date);
+            let band = rate.band(rate.slot(date)).context("No band")?;
+            Ok(band.name.clone())
+        };
+
+        // Saturday, Wednesday and a Thursday holiday at noon
+        ensure!(band((2024, 6, 1, 12))? == "Free Weekend");
+        ensure!(band((2024, 6, 5, 12))? == "Standard");
+        ensure!(band((2024, 12, 26, 12))? == "Free Weekend");
+
+        // Peak applies in the winter season only, December to February
+        ensure!(band((2024, 12, 4, 17))? == "Winter Peak");
+        ensure!(band((2024, 6, 5, 17))? == "Standard");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_time_of_use_export() -> anyhow::Result<()> {
+        let input = r#"
+            [[versions]]
+            name = "Time of Use Export"
+            start = "2024-01-01"
+            standing_charge = 0.5
+
+            [[versions.export]]
+            name = "Peak Export"
+            hours = [17, 18]
+            rate = 0.3
+
+            [[versions.export]]
+            name = "Standard Export"
+            hours = [
+                0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11,
+                12, 13, 14, 15, 16, 17, 18, 19, 20, 21, 22, 23
+            ]
+            rate = 0.15
+
+            [[versions.bands]]
+            name = "Standard"
+            hours = [
+                0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11,
+                12, 13, 14, 15, 16, 17, 18, 19, 20, 21, 22, 23
+            ]
+            rate = 0.3
+        "#;
+
+        let tariff = Tariff::from_toml(input)?;
+        let credit = |hour| -> anyhow::Result<f64> {
+            let date = Dublin
+                .with_ymd_and_hms(2024, 6, 1, hour, 0, 0)
+                .single()
+                .context("Failed to create DateTime<Tz> value")?;
+
+            Ok(tariff.rate(date).export_credit(2000_f64, date))
+        };
+
+        ensure!((credit(17)? - 0.6).abs() < 1e-9);
+        ensure!((credit(12)? - 0.3).abs() < 1e-9);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_bill_items() -> anyhow::Result<()> {
+        let input = r#"
+            [[credits]]
+            name = "Energy Credit"
+            date = "2024-06-02"
+            amount = 100.0
+
+            [[versions]]
+            name = "Billed"
+            start = "2024-01-01"
+            standing_charge = 0.5
+            pso_levy = 3.65
+            vat = 0.1
+            export = 0.2
+
+            [[versions.bands]]
+            name = "Standard"
+            hours = [
+                0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11,
+                12, 13, 14, 15, 16, 17, 18, 19, 20, 21, 22, 23
+            ]
+            rate = 0.3
+        "#;
+
+        let tariff = Tariff::from_toml(input)?;
+        let record = |day, hour, minute| -> anyhow::Result<SolarRecord> {
+            let date_time = Dublin
+                .with_ymd_and_hms(2024, 6, day, hour, minute, 0)
+                .single()
+                .context("Failed to create DateTime<Tz> value")?;
+
+            Ok(SolarRecord::new(
+                date_time,
+                Duration::hours(1),
+                0,
+                1000,
+                -1000,
+                0,
+                0,
+            ))
+        };
+
+        // 1kWh at 30c plus an hour of the 50c standing charge
+        let expected = (0.3 + 0.5 / 24_f64) * 1.1;
+        ensure!((record(1, 12, 0)?.cost(&tariff) - expected).abs() < 1e-9);
+        ensure!((record(2, 0, 30)?.cost(&tariff) - expected).abs() < 1e-9);
+
+        // A whole two-month cycle of levy, whatever part of it has records,
+        // less the credit dated within it
+        let date = |month, day| NaiveDate::from_ymd_opt(2024, month, day).context("Invalid date");
+        let levy = 3.65 * 2_f64 * 1.1;
+        let charges = tariff.period_charges(date(5, 1)?, date(6, 30)?, 2);
+        ensure!((charges - (levy - 100_f64)).abs() < 1e-9);
+        let charges = tariff.period_charges(date(7, 1)?, date(8, 31)?, 2);
+        ensure!((charges - levy).abs() < 1e-9);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_missing_hour() -> anyhow::Result<()> {
+        let input = r#"
+            [[versions]]
+            name = "Incomplete"
+            start = "2024-01-01"
+            standing_charge = 0.5
+            export = 0.2
+
+            [[versions.bands]]
+            name = "Day"
+            hours = [8, 9, 10]
+            rate = 0.3
+        "#;
+
+        ensure!(Tariff::from_toml(input).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_empty_billing_cycle() -> anyhow::Result<()> {
+        let input = r#"
+            [billing]
+            start = "2024-01-01"
+            months = 0
+
+            [[versions]]
+            name = "Flat"
+            start = "2024-01-01"
+            standing_charge = 0.5
+            export = 0.2
+
+            [[versions.bands]]
+            name = "All Day"
+            hours = [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17, 18, 19, 20, 21, 22, 23]
+            rate = 0.3
+        "#;
+
+        ensure!(Tariff::from_toml(input).is_err());
+        ensure!(Tariff::from_toml(&input.replace("months = 0", "months = 1")).is_ok());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_band_out_of_range() -> anyhow::Result<()> {
+        let tariff = |hours: &str, months: &str| {
+            Tariff::from_toml(&format!(
+                r#"
+                [[versions]]
+                name = "Typo"
+                start = "2024-01-01"
+                standing_charge = 0.5
+                export = 0.2
+
+                [[versions.bands]]
+                name = "Night"
+                hours = {hours}
+                months = {months}
+                rate = 0.2
+
+                [[versions.bands]]
+                name = "Standard"
+                hours = [
+                    0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11,
+                    12, 13, 14, 15, 16, 17, 18, 19, 20, 21, 22, 23
+                ]
+                rate = 0.3
+                "#
+            ))
+        };
+
+        ensure!(tariff("[23]", "[12]").is_ok());
+        ensure!(tariff("[24]", "[12]").is_err());
+        ensure!(tariff("[23]", "[13]").is_err());
+        ensure!(tariff("[23]", "[0]").is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_missing_day() -> anyhow::Result<()> {
+        let input = r#"
+            [[versions]]
+            name = "Weekdays Only"
+            start = "2024-01-01"
+            standing_charge = 0.5
+            export = 0.2
+
+            [[versions.bands]]
+            name = "Standard"
+            days = ["weekdays"]
+            hours = [
+                0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11,
+                12, 13, 14, 15, 16, 17, 18, 19, 20, 21, 22, 23
+            ]
+            rate = 0.3
+        "#;
+
+        ensure!(Tariff::from_toml(input).is_err());
+
+        Ok(())
+    }
+}